@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use crate::bundle::PatchManifest;
+use crate::hash::copy_with_hash;
+use crate::node::diff::{FileDetail, FileDiff};
+use crate::patch::{apply_diffs, ApplyOptions};
+
+const MANIFEST_ENTRY: &str = "manifest.bin";
+const DATA_PREFIX: &str = "data/";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    #[cfg(feature = "zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Picks the archive format from the file extension, if it is one.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            return Some(ArchiveFormat::TarGz);
+        }
+        #[cfg(feature = "zip")]
+        if name.ends_with(".zip") {
+            return Some(ArchiveFormat::Zip);
+        }
+        None
+    }
+}
+
+/// Streams the files written by `manifest` from `path` into a single archive at `output`.
+/// Files the manifest lists as duplicates are only stored once.
+///
+/// The manifest is stored as the first entry so readers can validate payload entries as they come.
+pub fn write_archive<P: AsRef<Path>, Q: AsRef<Path>>(path: P, manifest: &PatchManifest, output: Q, format: ArchiveFormat) -> io::Result<()> {
+    let path = path.as_ref();
+    let encoded = bincode::serialize(manifest).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let files = payload_files(manifest);
+
+    match format {
+        ArchiveFormat::TarGz => {
+            let encoder = GzEncoder::new(File::create(output)?, Compression::default());
+            let mut builder = tar::Builder::new(encoder);
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(encoded.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, MANIFEST_ENTRY, encoded.as_slice())?;
+
+            for detail in files {
+                log::info!("Adding file: {}", detail);
+                builder.append_path_with_name(detail.get_path(path), entry_name(detail))?;
+            }
+            builder.into_inner()?.finish()?;
+        },
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => {
+            use std::io::Write;
+            let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+            let mut writer = zip::ZipWriter::new(File::create(output)?);
+
+            writer.start_file(MANIFEST_ENTRY, options).map_err(zip_error)?;
+            writer.write_all(&encoded)?;
+
+            for detail in files {
+                log::info!("Adding file: {}", detail);
+                writer.start_file(entry_name(detail), options).map_err(zip_error)?;
+                io::copy(&mut File::open(detail.get_path(path))?, &mut writer)?;
+            }
+            writer.finish().map_err(zip_error)?;
+        },
+    }
+    Ok(())
+}
+
+/// Unpacks the payload of an archive into the patch folder `output` and returns its manifest.
+///
+/// Every extracted file is checked against the hash recorded in the manifest. Files the manifest
+/// lists as duplicates are not extracted.
+pub fn extract_archive<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, output: Q, format: ArchiveFormat) -> io::Result<PatchManifest> {
+    let mut extractor = Extractor::new(output.as_ref());
+
+    match format {
+        ArchiveFormat::TarGz => {
+            let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = entry.path()?.to_string_lossy().into_owned();
+                extractor.entry(&name, &mut entry)?;
+            }
+        },
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(archive)?).map_err(zip_error)?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i).map_err(zip_error)?;
+                let name = entry.name().to_string();
+                extractor.entry(&name, &mut entry)?;
+            }
+        },
+    }
+
+    extractor.finish()
+}
+
+/// Extracts an archive into a temporary patch folder and applies it to `install_dir`.
+pub fn apply_archive<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, install_dir: Q, format: ArchiveFormat) -> io::Result<PatchManifest> {
+    apply_archive_with_options(archive, install_dir, format, &ApplyOptions::default())
+}
+
+pub fn apply_archive_with_options<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, install_dir: Q, format: ArchiveFormat, options: &ApplyOptions) -> io::Result<PatchManifest> {
+    let staging = staging_dir();
+    let result = extract_archive(archive, &staging, format).and_then(|manifest| {
+        apply_diffs(install_dir.as_ref(), &manifest.diffs, &manifest.duplicates, options, |detail, _, staged| {
+            fs::copy(detail.get_path(&staging), staged).map(|_| ())
+        })?;
+        Ok(manifest)
+    });
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    result
+}
+
+struct Extractor<'a> {
+    output: &'a Path,
+    manifest: Option<PatchManifest>,
+    entries: BTreeMap<String, FileDetail>,
+    hashes: BTreeMap<String, String>,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(output: &'a Path) -> Self {
+        Extractor {
+            output,
+            manifest: None,
+            entries: BTreeMap::new(),
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    fn entry<R: Read>(&mut self, name: &str, rd: &mut R) -> io::Result<()> {
+        if name == MANIFEST_ENTRY {
+            let mut bytes = Vec::new();
+            rd.read_to_end(&mut bytes)?;
+            let manifest: PatchManifest = bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            for diff in &manifest.diffs {
+                diff.check_paths()?;
+            }
+            self.entries = payload_files(&manifest).into_iter().map(|detail| (entry_name(detail), detail.clone())).collect();
+            self.manifest = Some(manifest);
+            return Ok(());
+        }
+
+        if self.manifest.is_none() {
+            return Err(invalid_data(format!("Entry {} found before the manifest", name)));
+        }
+        // only entries listed in the manifest are written, and its paths were checked when it was read
+        let detail = self.entries.get(name).ok_or_else(|| invalid_data(format!("Unexpected entry {}", name)))?;
+        let target = detail.get_path(self.output);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        log::debug!("Extracting {} to {}", name, target.display());
+        let hash = copy_with_hash(rd, &mut File::create(&target)?)?;
+        self.hashes.insert(detail.to_string(), hash);
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<PatchManifest> {
+        let manifest = self.manifest.ok_or_else(|| invalid_data("Archive has no manifest".to_string()))?;
+        for (key, hash) in manifest.hashes.iter().filter(|(key, _)| !manifest.duplicates.contains_key(*key)) {
+            if self.hashes.get(key) != Some(hash) {
+                return Err(invalid_data(format!("Hash mismatch for {}", key)));
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+fn payload_files(manifest: &PatchManifest) -> Vec<&FileDetail> {
+    manifest.diffs.iter().filter_map(|diff| match diff {
+        FileDiff::Add(detail) | FileDiff::Change(detail) if detail.is_file && !manifest.duplicates.contains_key(&detail.to_string()) => Some(detail),
+        _ => None,
+    }).collect()
+}
+
+fn entry_name(detail: &FileDetail) -> String {
+    let relative = detail.get_path("");
+    let parts: Vec<_> = relative.components().filter_map(|c| match c {
+        Component::Normal(part) => Some(part.to_string_lossy()),
+        _ => None,
+    }).collect();
+    format!("{}{}", DATA_PREFIX, parts.join("/"))
+}
+
+fn staging_dir() -> PathBuf {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
+    std::env::temp_dir().join(format!("release-{}-{}", std::process::id(), nanos))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(feature = "zip")]
+fn zip_error(error: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
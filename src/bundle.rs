@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use crate::chunk::{changed_chunks, chunk_file, read_chunk, Chunk};
+use crate::data::{get_time, FileData};
+use crate::delta::{Delta, Signature};
+use crate::error::Result;
+use crate::hash::{calculate_hash, copy_with_hash};
+use crate::node::diff::{FileDetail, FileDiff};
+use crate::patch::{apply_diffs, ApplyOptions};
+use crate::signature::InstallSignature;
+
+/// Describes what a patch changes: the diff list, the versions it upgrades between
+/// and the expected hash of every file it writes.
+///
+/// Files listed in `deltas` are stored as a [`Delta`] against the installed file, keyed to
+/// the hash that installed file must have. Files listed in `duplicates` have the same content as
+/// an earlier file of the patch and are not stored again, keyed to that file. Files listed in
+/// `chunked` are rebuilt from their chunks: the ones the installed file already has are reused
+/// and only the others are stored, in the bundle's `chunks`.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PatchManifest {
+    pub from_version: u64,
+    pub to_version: u64,
+    pub time: u64,
+    pub diffs: Vec<FileDiff>,
+    pub hashes: BTreeMap<String, String>,
+    pub deltas: BTreeMap<String, String>,
+    pub duplicates: BTreeMap<String, String>,
+    pub chunked: BTreeMap<String, Vec<Chunk>>,
+}
+
+/// A patch as a single artifact: the manifest plus the content of every added or changed file.
+#[derive(Deserialize, Serialize)]
+pub struct PatchBundle {
+    pub manifest: PatchManifest,
+    pub payload: BTreeMap<String, Vec<u8>>,
+    /// Content of the new chunks of chunked files, keyed by hash.
+    pub chunks: BTreeMap<String, Vec<u8>>,
+}
+
+impl PatchManifest {
+    pub fn new(source: &FileData, target: &FileData) -> Result<Self> {
+        let diffs = source.diff(target)?;
+        let target_hashes: BTreeMap<String, String> = match target.root.as_ref() {
+            Some(root) => root.files().into_iter().map(|file| (file.get_path(), file.get_hash())).collect(),
+            None => BTreeMap::new(),
+        };
+
+        let mut hashes = BTreeMap::new();
+        let mut duplicates = BTreeMap::new();
+        let mut originals: HashMap<&String, String> = HashMap::new();
+        for diff in &diffs {
+            if let FileDiff::Add(detail) | FileDiff::Change(detail) = diff {
+                if !detail.is_file {
+                    continue;
+                }
+                let key = detail.to_string();
+                if let Some(hash) = target_hashes.get(&key) {
+                    match originals.get(hash) {
+                        Some(original) => {
+                            duplicates.insert(key.clone(), original.clone());
+                        }
+                        None => {
+                            originals.insert(hash, key.clone());
+                        }
+                    }
+                    hashes.insert(key, hash.clone());
+                }
+            }
+        }
+
+        Ok(PatchManifest {
+            from_version: source.version,
+            to_version: target.version,
+            time: get_time(),
+            diffs,
+            hashes,
+            deltas: BTreeMap::new(),
+            duplicates,
+            chunked: BTreeMap::new(),
+        })
+    }
+
+    pub fn applies_to(&self, data: &FileData) -> bool {
+        self.from_version == data.version
+    }
+}
+
+/// Provides file content while building a [`PatchBundle`].
+pub(crate) trait ContentSource {
+    /// Returns the new content of `detail`, which should hash to `hash`.
+    fn read(&self, detail: &FileDetail, hash: &str) -> io::Result<Vec<u8>>;
+
+    /// Returns the previous content of `detail`, which should hash to `hash`, to compute a delta against.
+    fn read_base(&self, _detail: &FileDetail, _hash: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Encodes `content` as a delta against the previous content of `detail`, which should hash to
+    /// `base_hash`, if that is smaller.
+    fn delta(&self, detail: &FileDetail, base_hash: &str, content: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        match self.read_base(detail, base_hash)? {
+            Some(old) => create_delta(detail, &old, base_hash, content),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the content of `chunk` of the new version of `detail`.
+    fn read_chunk(&self, detail: &FileDetail, hash: &str, chunk: &Chunk) -> io::Result<Vec<u8>> {
+        let content = self.read(detail, hash)?;
+        content.get(chunk.offset as usize..(chunk.offset + chunk.len as u64) as usize)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, format!("Chunk out of range: {}", detail)))
+    }
+}
+
+struct DirectorySource<'a> {
+    path: &'a Path,
+    base: Option<&'a Path>,
+}
+
+impl ContentSource for DirectorySource<'_> {
+    fn read(&self, detail: &FileDetail, _hash: &str) -> io::Result<Vec<u8>> {
+        fs::read(detail.get_path(self.path))
+    }
+
+    fn read_base(&self, detail: &FileDetail, _hash: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(base) = self.base else {
+            return Ok(None);
+        };
+        match fs::read(detail.get_path(base)) {
+            Ok(old) => Ok(Some(old)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_chunk(&self, detail: &FileDetail, _hash: &str, chunk: &Chunk) -> io::Result<Vec<u8>> {
+        read_chunk(detail.get_path(self.path), chunk)
+    }
+}
+
+struct SignatureSource<'a> {
+    path: &'a Path,
+    signatures: &'a BTreeMap<String, Signature>,
+}
+
+impl ContentSource for SignatureSource<'_> {
+    fn read(&self, detail: &FileDetail, _hash: &str) -> io::Result<Vec<u8>> {
+        fs::read(detail.get_path(self.path))
+    }
+
+    fn delta(&self, detail: &FileDetail, _base_hash: &str, content: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let delta = self.signatures.get(&detail.to_string()).map(|signature| Delta::new(signature, content).encode()).transpose()?;
+        Ok(delta.filter(|delta| delta.len() < content.len()))
+    }
+
+    fn read_chunk(&self, detail: &FileDetail, _hash: &str, chunk: &Chunk) -> io::Result<Vec<u8>> {
+        read_chunk(detail.get_path(self.path), chunk)
+    }
+}
+
+impl PatchBundle {
+    /// Builds a bundle upgrading `source` to `target`, reading file content from `path`.
+    ///
+    /// Changed files chunked in both `source` and `target` only store the chunks `source` lacks.
+    pub fn create<P: AsRef<Path>>(path: P, source: &FileData, target: &FileData) -> io::Result<Self> {
+        Self::build(&DirectorySource { path: path.as_ref(), base: None }, source, target)
+    }
+
+    /// Like [`PatchBundle::create`], but stores changed files as a delta against their copy
+    /// in `base`, the installed tree of `source`.
+    ///
+    /// A file falls back to a full copy when its base copy does not match `source` or the
+    /// delta is not smaller than the file.
+    pub fn create_with_delta<P: AsRef<Path>, Q: AsRef<Path>>(path: P, base: Q, source: &FileData, target: &FileData) -> io::Result<Self> {
+        Self::build(&DirectorySource { path: path.as_ref(), base: Some(base.as_ref()) }, source, target)
+    }
+
+    /// Builds a bundle upgrading the installed tree described by `install` to `target`, reading
+    /// file content from `path`.
+    ///
+    /// Unlike [`PatchBundle::create`] this does not assume the install matches a known release:
+    /// changed files are stored as deltas against the block signatures of what is actually
+    /// installed, and files the install has but `target` does not are removed.
+    pub fn create_for_install<P: AsRef<Path>>(path: P, install: &InstallSignature, target: &FileData) -> io::Result<Self> {
+        Self::build(&SignatureSource { path: path.as_ref(), signatures: &install.signatures }, &install.data, target)
+    }
+
+    pub(crate) fn build<S: ContentSource>(content_source: &S, source: &FileData, target: &FileData) -> io::Result<Self> {
+        let mut manifest = PatchManifest::new(source, target)?;
+        let source_hashes: BTreeMap<String, String> = match source.root.as_ref() {
+            Some(root) => root.files().into_iter().map(|file| (file.get_path(), file.get_hash())).collect(),
+            None => BTreeMap::new(),
+        };
+        let source_chunks = chunk_lists(source);
+        let target_chunks = chunk_lists(target);
+
+        let mut payload = BTreeMap::new();
+        let mut chunks = BTreeMap::new();
+        for diff in &manifest.diffs {
+            match diff {
+                FileDiff::Add(detail) | FileDiff::Change(detail) if detail.is_file => {
+                    let key = detail.to_string();
+                    if manifest.duplicates.contains_key(&key) {
+                        log::debug!("Skipping duplicate file: {}", detail);
+                        continue;
+                    }
+                    log::info!("Adding file: {}", detail);
+                    let hash = manifest.hashes.get(&key).map(String::as_str).unwrap_or_default();
+                    // files other entries duplicate must stay whole so they can be copied
+                    let is_original = manifest.duplicates.values().any(|original| *original == key);
+                    if let (FileDiff::Change(_), Some(old), Some(new)) = (diff, source_chunks.get(&key), target_chunks.get(&key)) {
+                        if !is_original {
+                            let changed = changed_chunks(old, new);
+                            log::debug!("Storing {} of {} chunks of {}", changed.len(), new.len(), detail);
+                            for chunk in changed {
+                                if chunks.contains_key(&chunk.hash) {
+                                    continue;
+                                }
+                                let data = content_source.read_chunk(detail, hash, chunk)?;
+                                if calculate_hash(&data) != chunk.hash {
+                                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("File changed since it was chunked: {}", detail)));
+                                }
+                                chunks.insert(chunk.hash.clone(), data);
+                            }
+                            manifest.chunked.insert(key, new.to_vec());
+                            continue;
+                        }
+                    }
+                    let content = content_source.read(detail, hash)?;
+                    let delta = match (source_hashes.get(&key), diff) {
+                        (Some(base_hash), FileDiff::Change(_)) if !is_original => content_source.delta(detail, base_hash, &content)?,
+                        _ => None,
+                    };
+                    match delta {
+                        Some(delta) => {
+                            log::debug!("Storing {} as a delta of {} bytes", detail, delta.len());
+                            manifest.deltas.insert(key.clone(), source_hashes[&key].clone());
+                            payload.insert(key, delta);
+                        }
+                        None => {
+                            payload.insert(key, content);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(PatchBundle { manifest, payload, chunks })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut decoder = GzDecoder::new(file);
+        let mut bytes = Vec::new();
+        decoder.read_to_end(&mut bytes)?;
+        bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let encoded = bincode::serialize(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let file = File::create(path)?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(&encoded)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Checks that every file the manifest writes is present in the payload with the expected hash.
+    pub fn verify(&self) -> io::Result<()> {
+        for diff in &self.manifest.diffs {
+            if let FileDiff::Add(detail) | FileDiff::Change(detail) = diff {
+                if !detail.is_file {
+                    continue;
+                }
+                let key = detail.to_string();
+                let key = self.manifest.duplicates.get(&key).cloned().unwrap_or(key);
+                // like deltas, chunks found in the installed file are only checked once applied
+                if self.manifest.chunked.contains_key(&key) {
+                    continue;
+                }
+                let content = self.payload.get(&key).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Missing payload for {}", key))
+                })?;
+                // a delta can only be checked once it is applied to the installed file
+                if self.manifest.deltas.contains_key(&key) {
+                    Delta::decode(content)?;
+                } else if self.manifest.hashes.get(&key) != Some(&calculate_hash(content)) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Hash mismatch for {}", key)));
+                }
+            }
+        }
+        for (hash, content) in &self.chunks {
+            if calculate_hash(content) != *hash {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Hash mismatch for chunk {}", hash)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies the bundle and applies it to the installed tree at `install_dir`.
+    pub fn apply<P: AsRef<Path>>(&self, install_dir: P) -> io::Result<()> {
+        self.apply_with_options(install_dir, &ApplyOptions::default())
+    }
+
+    pub fn apply_with_options<P: AsRef<Path>>(&self, install_dir: P, options: &ApplyOptions) -> io::Result<()> {
+        self.verify()?;
+        let manifest = &self.manifest;
+        apply_diffs(install_dir.as_ref(), &manifest.diffs, &manifest.duplicates, options, |detail, installed, staged| {
+            let key = detail.to_string();
+            if let Some(chunks) = self.manifest.chunked.get(&key) {
+                return self.write_chunked(&key, chunks, installed, staged);
+            }
+            let content = self.payload.get(&key).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Missing payload for {}", key))
+            })?;
+            let Some(base_hash) = self.manifest.deltas.get(&key) else {
+                return fs::write(staged, content);
+            };
+
+            let old = fs::read(installed)?;
+            if calculate_hash(&old) != *base_hash {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Installed file does not match the delta base: {}", key)));
+            }
+            let new = Delta::decode(content)?.apply(&old)?;
+            if self.manifest.hashes.get(&key) != Some(&calculate_hash(&new)) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Hash mismatch for {}", key)));
+            }
+            fs::write(staged, new)
+        })
+    }
+
+    fn write_chunked(&self, key: &str, chunks: &[Chunk], installed: &Path, staged: &Path) -> io::Result<()> {
+        let old: HashMap<String, Chunk> = if installed.is_file() {
+            chunk_file(installed)?.into_iter().map(|chunk| (chunk.hash.clone(), chunk)).collect()
+        } else {
+            HashMap::new()
+        };
+
+        let mut file = File::create(staged)?;
+        for chunk in chunks {
+            match (self.chunks.get(&chunk.hash), old.get(&chunk.hash)) {
+                (Some(content), _) => file.write_all(content)?,
+                (None, Some(old_chunk)) => file.write_all(&read_chunk(installed, old_chunk)?)?,
+                (None, None) => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Chunk {} of {} is missing", chunk.hash, key)));
+                }
+            }
+        }
+        drop(file);
+
+        let hash = copy_with_hash(&mut File::open(staged)?, &mut io::sink())?;
+        if self.manifest.hashes.get(key) != Some(&hash) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Hash mismatch for {}", key)));
+        }
+        Ok(())
+    }
+}
+
+fn chunk_lists(data: &FileData) -> BTreeMap<String, &Vec<Chunk>> {
+    match data.root.as_ref() {
+        Some(root) => root.files().into_iter().filter(|file| !file.chunks.is_empty()).map(|file| (file.get_path(), &file.chunks)).collect(),
+        None => BTreeMap::new(),
+    }
+}
+
+fn create_delta(detail: &FileDetail, old: &[u8], base_hash: &str, content: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    if calculate_hash(old) != base_hash {
+        log::warn!("Base file does not match the source file data: {}", detail);
+        return Ok(None);
+    }
+
+    let delta = Delta::from_files(old, content).encode()?;
+    Ok(Some(delta).filter(|delta| delta.len() < content.len()))
+}
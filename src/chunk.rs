@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::data::FileData;
+use crate::hash::calculate_hash;
+use crate::node::diff::FileDetail;
+
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024;
+pub const AVG_CHUNK_SIZE: usize = 64 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+// normalized chunking: harder to cut before the average size, easier after it
+const MASK_SMALL: u64 = ((1 << 18) - 1) << 46;
+const MASK_LARGE: u64 = ((1 << 14) - 1) << 50;
+
+static GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64, so the table and therefore every chunk boundary is fixed across builds
+    let mut table = [0u64; 256];
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// A content-defined slice of a file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Chunk {
+    pub offset: u64,
+    pub len: u32,
+    pub hash: String,
+}
+
+/// Splits a stream into content-defined chunks with a gear rolling hash, in the manner of FastCDC.
+///
+/// Boundaries depend only on the bytes around them, so an edit only changes the chunks it touches.
+pub struct Chunker<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    offset: u64,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R) -> Self {
+        Chunker {
+            reader,
+            buffer: Vec::with_capacity(MAX_CHUNK_SIZE),
+            offset: 0,
+            eof: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        while !self.eof && self.buffer.len() < MAX_CHUNK_SIZE {
+            let len = self.buffer.len();
+            self.buffer.resize(MAX_CHUNK_SIZE, 0);
+            match self.reader.read(&mut self.buffer[len..]) {
+                Ok(n) => {
+                    self.buffer.truncate(len + n);
+                    self.eof = n == 0;
+                }
+                Err(e) => {
+                    self.buffer.truncate(len);
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<(Chunk, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let len = cut_point(&self.buffer);
+        let rest = self.buffer.split_off(len);
+        let data = std::mem::replace(&mut self.buffer, rest);
+        let chunk = Chunk {
+            offset: self.offset,
+            len: len as u32,
+            hash: calculate_hash(&data),
+        };
+        self.offset += len as u64;
+        Some(Ok((chunk, data)))
+    }
+}
+
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let normal = end.min(AVG_CHUNK_SIZE);
+
+    let mut hash = 0u64;
+    for (i, byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        let mask = if i < normal { MASK_SMALL } else { MASK_LARGE };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+pub fn chunk_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<Chunk>> {
+    Chunker::new(File::open(path)?).map(|chunk| chunk.map(|(chunk, _)| chunk)).collect()
+}
+
+pub fn read_chunk<P: AsRef<Path>>(path: P, chunk: &Chunk) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(chunk.offset))?;
+    let mut data = vec![0; chunk.len as usize];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Returns the chunks of `new` whose content is not found anywhere in `old`.
+pub fn changed_chunks<'a>(old: &[Chunk], new: &'a [Chunk]) -> Vec<&'a Chunk> {
+    let old: HashSet<&str> = old.iter().map(|chunk| chunk.hash.as_str()).collect();
+    new.iter().filter(|chunk| !old.contains(chunk.hash.as_str())).collect()
+}
+
+/// Computes the chunk list of every file of `data` at least `min_size` bytes long, reading the
+/// files from `path`. Returns the number of files chunked.
+pub fn add_chunks<P: AsRef<Path>>(data: &mut FileData, path: P, min_size: u64) -> io::Result<usize> {
+    let path = path.as_ref();
+    let mut chunked = 0;
+    if let Some(root) = data.root.as_mut() {
+        for file in root.files_mut() {
+            let file_path = FileDetail::from_file(file).get_path(path);
+            if fs::metadata(&file_path)?.len() < min_size {
+                continue;
+            }
+            log::debug!("Chunking: {}", file.get_path());
+            file.chunks = chunk_file(file_path)?;
+            chunked += 1;
+        }
+    }
+    Ok(chunked)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{changed_chunks, Chunker, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE};
+
+    fn random_bytes(len: usize, mut seed: u64) -> Vec<u8> {
+        (0..len).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u8
+        }).collect()
+    }
+
+    #[test]
+    fn test_chunks_cover_data() {
+        let data = random_bytes(2 * 1024 * 1024 + 123, 1);
+        let chunks: Vec<_> = Chunker::new(data.as_slice()).map(Result::unwrap).collect();
+        assert!(chunks.len() > 1);
+
+        let mut offset = 0;
+        for (i, (chunk, bytes)) in chunks.iter().enumerate() {
+            assert_eq!(chunk.offset, offset);
+            assert_eq!(bytes.as_slice(), &data[offset as usize..offset as usize + chunk.len as usize]);
+            assert!(chunk.len as usize <= MAX_CHUNK_SIZE);
+            assert!(chunk.len as usize >= MIN_CHUNK_SIZE || i == chunks.len() - 1);
+            offset += chunk.len as u64;
+        }
+        assert_eq!(offset, data.len() as u64);
+    }
+
+    #[test]
+    fn test_insert_changes_few_chunks() {
+        let old = random_bytes(4 * 1024 * 1024, 2);
+        let mut new = old.clone();
+        new.splice(1000..1000, b"inserted".iter().copied());
+
+        let old_chunks: Vec<_> = Chunker::new(old.as_slice()).map(|c| c.unwrap().0).collect();
+        let new_chunks: Vec<_> = Chunker::new(new.as_slice()).map(|c| c.unwrap().0).collect();
+        assert!(changed_chunks(&old_chunks, &new_chunks).len() <= 2);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::error::{Error, Result};
+use crate::rules::RuleSet;
+
+/// Project configuration read by default from the working directory.
+pub const CONFIG_FILE: &str = "release.toml";
+
+/// Named release targets, read from a TOML file such as:
+///
+/// ```toml
+/// [targets.game]
+/// path = "build/game"
+/// manifest = "releases/game.bin.gz"
+/// output = "releases/game-patch.tar.gz"
+/// format = "tar-gz"
+/// ignore = ["*.pdb"]
+/// repository = "repo"
+/// channel = "beta"
+/// ```
+///
+/// Relative paths are resolved against the directory of the configuration file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub targets: BTreeMap<String, TargetConfig>,
+}
+
+/// How a target builds a release: the tree to scan, the manifest of the previous release, which
+/// the new one replaces, and where the patch between them goes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    /// Directory to scan.
+    pub path: PathBuf,
+    /// File data of the previous release, replaced by the new one.
+    pub manifest: PathBuf,
+    /// Patch folder or file to write.
+    pub output: PathBuf,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// Exclude patterns, applied after the includes.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Include patterns, see [`RuleSet`].
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Rules file, applied before the include and ignore patterns.
+    pub rules: Option<PathBuf>,
+    /// Splits files of at least this many bytes into chunks.
+    pub chunk: Option<u64>,
+    #[serde(default)]
+    pub hash: HashAlgorithm,
+    /// Repository to publish the release to.
+    pub repository: Option<PathBuf>,
+    /// Channel of the repository to point at the release.
+    pub channel: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// A folder of the changed files.
+    #[default]
+    Folder,
+    /// A single [`PatchBundle`](crate::bundle::PatchBundle) file.
+    Bundle,
+    TarGz,
+    #[cfg(feature = "zip")]
+    Zip,
+}
+
+/// Hash recorded for each file. Manifests only use SHA-256 so far, so it is the only choice.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+}
+
+impl ProjectConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(path.to_path_buf()),
+            _ => Error::Io(e),
+        })?;
+        let mut config = ProjectConfig::from_text(&text)?;
+        if let Some(base) = path.parent() {
+            config.resolve(base);
+        }
+        Ok(config)
+    }
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let config: ProjectConfig = toml::from_str(text).map_err(|e| Error::Config(e.to_string()))?;
+        for (name, target) in &config.targets {
+            if target.channel.is_some() && target.repository.is_none() {
+                return Err(Error::Config(format!("target {} sets a channel without a repository", name)));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn target(&self, name: &str) -> Result<&TargetConfig> {
+        self.targets.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.targets.keys().map(String::as_str).collect();
+            Error::Config(format!("no target {}, expected one of: {}", name, names.join(", ")))
+        })
+    }
+
+    /// Makes the relative paths of every target relative to `base` instead.
+    pub fn resolve<P: AsRef<Path>>(&mut self, base: P) {
+        let base = base.as_ref();
+        for target in self.targets.values_mut() {
+            for path in [&mut target.path, &mut target.manifest, &mut target.output] {
+                *path = base.join(&*path);
+            }
+            for path in [&mut target.rules, &mut target.repository].into_iter().flatten() {
+                *path = base.join(&*path);
+            }
+        }
+    }
+}
+
+impl TargetConfig {
+    /// The `.releaseignore` of the target path, then its rules file, include and ignore patterns.
+    pub fn rules(&self) -> io::Result<RuleSet> {
+        let mut rules = RuleSet::load(&self.path)?;
+        if let Some(file) = &self.rules {
+            rules.extend(RuleSet::from_file(file)?);
+        }
+        for pattern in &self.include {
+            rules.include(pattern);
+        }
+        for pattern in &self.ignore {
+            rules.exclude(pattern);
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::config::{OutputFormat, ProjectConfig};
+
+    #[test]
+    fn test_parse_config() {
+        let mut config = ProjectConfig::from_text(r#"
+            [targets.game]
+            path = "build/game"
+            manifest = "releases/game.bin.gz"
+            output = "releases/game.tar.gz"
+            format = "tar-gz"
+            ignore = ["*.pdb"]
+            repository = "repo"
+            channel = "beta"
+
+            [targets.tools]
+            path = "build/tools"
+            manifest = "releases/tools.bin.gz"
+            output = "releases/tools"
+        "#).unwrap();
+        config.resolve("project");
+
+        let game = config.target("game").unwrap();
+        assert_eq!(game.format, OutputFormat::TarGz);
+        assert_eq!(game.path, Path::new("project").join("build/game"));
+        assert_eq!(game.repository.as_deref(), Some(Path::new("project").join("repo").as_path()));
+        assert_eq!(config.target("tools").unwrap().format, OutputFormat::Folder);
+        assert!(config.target("missing").unwrap_err().to_string().contains("game, tools"));
+
+        assert!(ProjectConfig::from_text("[targets.a]\npath = \"a\"\nmanifest = \"m\"\noutput = \"o\"\nchannel = \"beta\"\n").is_err());
+        assert!(ProjectConfig::from_text("[targets.a]\npath = \"a\"\nmanifest = \"m\"\noutput = \"o\"\nhash = \"md5\"\n").is_err());
+    }
+}
@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::{Read, Write};
@@ -6,33 +9,88 @@ use std::time::SystemTime;
 use flate2::Compression;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use crate::error::{Error, Result};
+use crate::format;
 use crate::node::diff::FileDiff;
 use crate::node::dir::DirectoryNode;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct FileData {
     pub path: String,
     pub version: u64,
     pub time: u64,
     pub root: Option<DirectoryNode>,
+    pub metadata: ReleaseMetadata,
 }
 
-fn get_time() -> u64 {
-    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
+/// Human-readable formats a [`FileData`] can be exported to and imported from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextFormat {
+    Json,
+    Toml,
 }
 
-impl Default for FileData {
-    fn default() -> Self {
-        FileData {
-            path: String::new(),
-            version: 0,
-            time: 0,
-            root: None,
+impl TextFormat {
+    /// Picks the format from the file extension, if it is one.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()?.to_lowercase().as_str() {
+            "json" => Some(TextFormat::Json),
+            "toml" => Some(TextFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// Describes the release a [`FileData`] belongs to.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseMetadata {
+    pub semver: Option<Version>,
+    pub notes: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    /// Oldest updater able to install the release.
+    pub min_updater_version: Option<Version>,
+    pub build_id: Option<String>,
+}
+
+/// Version of this updater, compared against [`ReleaseMetadata::min_updater_version`].
+pub fn updater_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).unwrap()
+}
+
+impl ReleaseMetadata {
+    /// Whether an updater at version `updater` can install the release.
+    pub fn supports_updater(&self, updater: &Version) -> bool {
+        self.min_updater_version.as_ref().is_none_or(|min| updater >= min)
+    }
+}
+
+impl fmt::Display for ReleaseMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(semver) = &self.semver {
+            writeln!(f, "Version: {}", semver)?;
+        }
+        if let Some(build_id) = &self.build_id {
+            writeln!(f, "Build: {}", build_id)?;
+        }
+        if let Some(min) = &self.min_updater_version {
+            writeln!(f, "Minimum updater: {}", min)?;
+        }
+        for (key, value) in &self.labels {
+            writeln!(f, "Label: {}={}", key, value)?;
+        }
+        if let Some(notes) = &self.notes {
+            writeln!(f, "Notes:\n{}", notes)?;
         }
+        Ok(())
     }
 }
 
+pub(crate) fn get_time() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
+}
+
 impl FileData {
     pub fn new(path: String, version: u64, root: DirectoryNode) -> Self {
         FileData {
@@ -40,31 +98,68 @@ impl FileData {
             version,
             time: get_time(),
             root: Some(root),
+            metadata: ReleaseMetadata::default(),
         }
     }
 
-    pub fn load<P: AsRef<Path>>(path: P) -> Self {
-        match File::open(path) {
-            Ok(file) => {
-                let mut decoder = GzDecoder::new(file);
-                let mut bytes = Vec::new();
-                decoder.read_to_end(&mut bytes).unwrap();
-                let mut file: FileData = bincode::deserialize(&bytes).unwrap_or_default();
-                if file.root.as_ref().is_some(){
-                    file.root.as_mut().unwrap().restore_path(None);
-                }
-                file
-            },
-            Err(_) => FileData::default(),
+    /// Reads a file data written by [`FileData::save`], or by any earlier version of it.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(Error::NotFound(path.to_path_buf())),
+            Err(e) => return Err(e.into()),
+        };
+        let mut bytes = Vec::new();
+        GzDecoder::new(file).read_to_end(&mut bytes).map_err(Error::CorruptCompression)?;
+        let mut file = format::decode(&bytes)?;
+        if let Some(root) = file.root.as_mut() {
+            root.restore_path(None);
         }
+        Ok(file)
+    }
+
+    /// Writes the whole file data, tree and metadata included, as text.
+    pub fn export<P: AsRef<Path>>(&self, path: P, format: TextFormat) -> Result<()> {
+        fs::write(path, self.to_text(format)?)?;
+        Ok(())
+    }
+
+    /// Reads a file data written by [`FileData::export`], possibly edited since.
+    pub fn import<P: AsRef<Path>>(path: P, format: TextFormat) -> Result<Self> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(Error::NotFound(path.to_path_buf())),
+            Err(e) => return Err(e.into()),
+        };
+        Self::from_text(&text, format)
+    }
+
+    pub fn to_text(&self, format: TextFormat) -> Result<String> {
+        match format {
+            TextFormat::Json => serde_json::to_string_pretty(self).map_err(|e| Error::Encode(e.to_string())),
+            TextFormat::Toml => toml::to_string_pretty(self).map_err(|e| Error::Encode(e.to_string())),
+        }
+    }
+
+    pub fn from_text(text: &str, format: TextFormat) -> Result<Self> {
+        let mut file: FileData = match format {
+            TextFormat::Json => serde_json::from_str(text).map_err(|e| Error::Decode(e.to_string()))?,
+            TextFormat::Toml => toml::from_str(text).map_err(|e| Error::Decode(e.to_string()))?,
+        };
+        if let Some(root) = file.root.as_mut() {
+            root.restore_path(None);
+        }
+        Ok(file)
     }
 
     pub fn get_path(&self) -> String {
         self.path.clone()
     }
 
-    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let encoded = bincode::serialize(self).expect("Serialization failed");
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let encoded = format::encode(self)?;
         let file = File::create(path)?;
         let mut encoder = GzEncoder::new(file, Compression::default());
         encoder.write_all(&encoded)?;
@@ -72,20 +167,17 @@ impl FileData {
         Ok(())
     }
 
-    pub fn diff(&self, other: &FileData) -> Vec<FileDiff> {
+    /// Lists the changes turning `self` into `other`; both need a root.
+    pub fn diff(&self, other: &FileData) -> Result<Vec<FileDiff>> {
         // if self.version > other.version {
         //     log::warn!("Version is older: source v{} > targe v{}", self.version, other.version);
         // }
         // if self.time != other.time {
         //     diffs.push(format!("Times differ: {} != {}", self.time, other.time));
         // }
-        if self.root.is_none() {
-            log::error!("Source root is none");
+        match (self.root.as_ref(), other.root.as_ref()) {
+            (Some(source), Some(target)) => Ok(source.get_update_list(target)),
+            _ => Err(Error::EmptyRoot),
         }
-        if other.root.is_none() {
-            log::error!("Target root is none");
-        }
-
-        self.root.as_ref().unwrap().get_update_list(other.root.as_ref().unwrap())
     }
 }
\ No newline at end of file
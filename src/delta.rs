@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::io;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const MIN_BLOCK_SIZE: usize = 256;
+const MAX_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Weak and strong checksums of every full block of a file, enough to compute a [`Delta`]
+/// against it without having the file itself.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Signature {
+    pub block_size: u32,
+    pub blocks: Vec<BlockSignature>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: [u8; 32],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DeltaOp {
+    /// Copies `len` bytes starting at `offset` of the old file.
+    Copy { offset: u64, len: u64 },
+    /// Inserts literal bytes.
+    Insert(Vec<u8>),
+}
+
+/// Instructions rebuilding a new file from an old one.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Delta {
+    pub ops: Vec<DeltaOp>,
+}
+
+/// Picks a block size close to the square root of the file length.
+pub fn block_size_for(len: usize) -> usize {
+    ((len as f64).sqrt() as usize).clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
+}
+
+impl Signature {
+    pub fn new(old: &[u8], block_size: usize) -> Self {
+        let blocks = old.chunks_exact(block_size).map(|block| BlockSignature {
+            weak: RollingChecksum::new(block).value(),
+            strong: strong_hash(block),
+        }).collect();
+
+        Signature {
+            block_size: block_size as u32,
+            blocks,
+        }
+    }
+}
+
+impl Delta {
+    /// Computes the delta between the file described by `signature` and `new`.
+    pub fn new(signature: &Signature, new: &[u8]) -> Self {
+        let block_size = signature.block_size as usize;
+        let mut delta = Delta::default();
+        if block_size == 0 || signature.blocks.is_empty() || new.len() < block_size {
+            delta.insert(new);
+            return delta;
+        }
+
+        let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, block) in signature.blocks.iter().enumerate() {
+            index.entry(block.weak).or_default().push(i);
+        }
+
+        let mut literal = 0;
+        let mut i = 0;
+        let mut checksum = RollingChecksum::new(&new[..block_size]);
+        loop {
+            let found = index.get(&checksum.value()).and_then(|candidates| {
+                let strong = strong_hash(&new[i..i + block_size]);
+                candidates.iter().find(|&&c| signature.blocks[c].strong == strong).copied()
+            });
+
+            match found {
+                Some(block) => {
+                    delta.insert(&new[literal..i]);
+                    delta.copy((block * block_size) as u64, block_size as u64);
+                    i += block_size;
+                    literal = i;
+                    if i + block_size > new.len() {
+                        break;
+                    }
+                    checksum = RollingChecksum::new(&new[i..i + block_size]);
+                }
+                None => {
+                    if i + block_size >= new.len() {
+                        break;
+                    }
+                    checksum.roll(new[i], new[i + block_size]);
+                    i += 1;
+                }
+            }
+        }
+        delta.insert(&new[literal..]);
+        delta
+    }
+
+    /// Computes the delta between `old` and `new`.
+    pub fn from_files(old: &[u8], new: &[u8]) -> Self {
+        Delta::new(&Signature::new(old, block_size_for(old.len())), new)
+    }
+
+    /// Rebuilds the new file from `old`.
+    pub fn apply(&self, old: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    let bytes = usize::try_from(*offset).ok()
+                        .zip(usize::try_from(*len).ok())
+                        .and_then(|(start, len)| start.checked_add(len).map(|end| (start, end)))
+                        .and_then(|(start, end)| old.get(start..end))
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Delta copies past the end of the old file"))?;
+                    out.extend_from_slice(bytes);
+                }
+                DeltaOp::Insert(bytes) => out.extend_from_slice(bytes),
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        bincode::deserialize(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn insert(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        match self.ops.last_mut() {
+            Some(DeltaOp::Insert(last)) => last.extend_from_slice(bytes),
+            _ => self.ops.push(DeltaOp::Insert(bytes.to_vec())),
+        }
+    }
+
+    fn copy(&mut self, offset: u64, len: u64) {
+        if let Some(DeltaOp::Copy { offset: last_offset, len: last_len }) = self.ops.last_mut() {
+            if *last_offset + *last_len == offset {
+                *last_len += len;
+                return;
+            }
+        }
+        self.ops.push(DeltaOp::Copy { offset, len });
+    }
+}
+
+fn strong_hash(block: &[u8]) -> [u8; 32] {
+    Sha256::digest(block).into()
+}
+
+/// The rsync weak checksum, which can slide over a window one byte at a time.
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingChecksum {
+    fn new(block: &[u8]) -> Self {
+        let len = block.len() as u32;
+        let mut a: u32 = 0;
+        let mut b: u32 = 0;
+        for (i, &byte) in block.iter().enumerate() {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+        }
+        RollingChecksum { a, b, len }
+    }
+
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32);
+        self.b = self.b.wrapping_sub(self.len.wrapping_mul(out as u32)).wrapping_add(self.a);
+    }
+
+    fn value(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delta::{Delta, DeltaOp};
+
+    fn sample(len: usize) -> Vec<u8> {
+        let mut state: u32 = 0x1234_5678;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let old = sample(100_000);
+        let mut new = old.clone();
+        new[50_000] ^= 0xff;
+        new.splice(10_000..10_000, b"inserted".iter().copied());
+        new.drain(80_000..80_100);
+
+        let delta = Delta::from_files(&old, &new);
+        assert_eq!(delta.apply(&old).unwrap(), new);
+        assert!(delta.encode().unwrap().len() < new.len() / 10);
+    }
+
+    #[test]
+    fn test_delta_unrelated() {
+        let old = sample(4096);
+        let new: Vec<u8> = old.iter().map(|b| b.wrapping_add(1)).collect();
+
+        let delta = Delta::from_files(&old, &new);
+        assert_eq!(delta.ops, vec![DeltaOp::Insert(new.clone())]);
+        assert_eq!(delta.apply(&old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_delta_copy_out_of_range() {
+        let old = sample(16);
+        for op in [DeltaOp::Copy { offset: 8, len: 9 }, DeltaOp::Copy { offset: u64::MAX, len: 2 }] {
+            let delta = Delta { ops: vec![op] };
+            assert_eq!(delta.apply(&old).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+}
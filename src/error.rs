@@ -0,0 +1,42 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("File not found: {0}")]
+    NotFound(PathBuf),
+    #[error("Corrupt compression: {0}")]
+    CorruptCompression(io::Error),
+    #[error("Cannot decode file data: {0}")]
+    Decode(String),
+    #[error("Cannot encode file data: {0}")]
+    Encode(String),
+    #[error("Unsupported file data format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid manifest entry: {0}")]
+    InvalidEntry(String),
+    #[error("Invalid hash: {0}")]
+    InvalidHash(String),
+    #[error("File data has no root")]
+    EmptyRoot,
+    #[error("Invalid config: {0}")]
+    Config(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(e) => e,
+            Error::NotFound(_) => io::Error::new(io::ErrorKind::NotFound, error),
+            Error::InvalidInput(_) => io::Error::new(io::ErrorKind::InvalidInput, error),
+            _ => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::data::{FileData, ReleaseMetadata};
+use crate::error::{Error, Result};
+use crate::node::dir::DirectoryNode;
+use crate::node::file::{is_valid_hash, FileNode};
+use crate::node::Node;
+
+/// Marks a [`FileData`] written with a format header.
+pub(crate) const MAGIC: &[u8; 4] = b"RLFD";
+/// Layout written by [`encode`].
+///
+/// Files without a header use the original layout, 1. Layout 2 added the chunk list and size of
+/// each file and the release metadata.
+pub(crate) const FORMAT_VERSION: u32 = 2;
+
+pub(crate) fn encode(data: &FileData) -> Result<Vec<u8>> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend(bincode::serialize(data).map_err(|e| Error::Encode(e.to_string()))?);
+    Ok(bytes)
+}
+
+/// Reads a [`FileData`] in any layout, upgrading it to the current one.
+pub(crate) fn decode(bytes: &[u8]) -> Result<FileData> {
+    if let Some(rest) = bytes.strip_prefix(MAGIC) {
+        if rest.len() < 4 {
+            return Err(Error::Decode("Truncated file data header".to_string()));
+        }
+        let (version, payload) = rest.split_at(4);
+        return decode_version(u32::from_le_bytes(version.try_into().unwrap()), payload);
+    }
+
+    // without a header the layout is unknown, so try the current one first; each layout reads
+    // different fields, so only the right one consumes exactly every byte
+    [FORMAT_VERSION, 1].into_iter()
+        .find_map(|version| decode_version(version, bytes).ok())
+        .ok_or_else(|| Error::Decode("Unrecognized file data layout".to_string()))
+}
+
+fn decode_version(version: u32, payload: &[u8]) -> Result<FileData> {
+    match version {
+        1 => strict::<LegacyFileData>(payload).and_then(FileData::try_from),
+        FORMAT_VERSION => strict::<FileData>(payload),
+        _ => Err(Error::UnsupportedVersion(version)),
+    }
+}
+
+/// Same encoding as `bincode::deserialize`, but trailing bytes are an error.
+fn strict<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+        .map_err(|e| Error::Decode(e.to_string()))
+}
+
+#[derive(Deserialize, Serialize)]
+struct LegacyFileData {
+    path: String,
+    version: u64,
+    time: u64,
+    root: Option<LegacyDirectory>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct LegacyDirectory {
+    name: String,
+    children: Vec<LegacyNode>,
+}
+
+#[derive(Deserialize, Serialize)]
+enum LegacyNode {
+    File(LegacyFileNode),
+    Directory(LegacyDirectory),
+}
+
+#[derive(Deserialize, Serialize)]
+struct LegacyFileNode {
+    name: String,
+    last_modified: u64,
+    hash: String,
+}
+
+impl TryFrom<LegacyFileNode> for FileNode {
+    type Error = Error;
+
+    /// Keeps the hash unless it is blank, as for a file that was never hashed.
+    fn try_from(file: LegacyFileNode) -> Result<Self> {
+        let mut node = FileNode {
+            path: None,
+            name: file.name,
+            last_modified: file.last_modified,
+            size: None,
+            hash: [' '; 64],
+            chunks: Vec::new(),
+        };
+        if is_valid_hash(&file.hash) {
+            node.set_hash(file.hash);
+        } else if !file.hash.trim().is_empty() {
+            return Err(Error::Decode(format!("Invalid hash for {}", node.name)));
+        }
+        Ok(node)
+    }
+}
+
+impl TryFrom<LegacyDirectory> for DirectoryNode {
+    type Error = Error;
+
+    fn try_from(dir: LegacyDirectory) -> Result<Self> {
+        let mut node = DirectoryNode::new(dir.name, None);
+        for child in dir.children {
+            node.add_child(match child {
+                LegacyNode::File(file) => Node::File(file.try_into()?),
+                LegacyNode::Directory(dir) => Node::Directory(dir.try_into()?),
+            });
+        }
+        Ok(node)
+    }
+}
+
+impl TryFrom<LegacyFileData> for FileData {
+    type Error = Error;
+
+    fn try_from(data: LegacyFileData) -> Result<Self> {
+        Ok(FileData {
+            path: data.path,
+            version: data.version,
+            time: data.time,
+            root: data.root.map(DirectoryNode::try_from).transpose()?,
+            metadata: ReleaseMetadata::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{decode, encode, LegacyDirectory, LegacyFileData, LegacyFileNode, LegacyNode};
+
+    #[test]
+    fn test_decode_original_layout() {
+        let legacy = LegacyFileData {
+            path: "release".to_string(),
+            version: 7,
+            time: 1,
+            root: Some(LegacyDirectory {
+                name: ".".to_string(),
+                children: vec![LegacyNode::File(LegacyFileNode {
+                    name: "a.txt".to_string(),
+                    last_modified: 2,
+                    hash: "a".repeat(64),
+                })],
+            }),
+        };
+        let data = decode(&bincode::serialize(&legacy).unwrap()).unwrap();
+        assert_eq!(data.version, 7);
+        let files = data.root.as_ref().unwrap().files();
+        assert_eq!(files[0].get_hash(), "a".repeat(64));
+        assert!(files[0].chunks.is_empty());
+        assert_eq!(files[0].size, None);
+
+        let headerless = decode(&bincode::serialize(&data).unwrap()).unwrap();
+        assert_eq!(headerless.root.as_ref().unwrap().files()[0].get_hash(), "a".repeat(64));
+
+        let encoded = encode(&data).unwrap();
+        assert_eq!(decode(&encoded).unwrap().version, 7);
+        assert!(decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid_legacy_hash() {
+        let legacy = |hash: String| LegacyFileData {
+            path: "release".to_string(),
+            version: 1,
+            time: 1,
+            root: Some(LegacyDirectory {
+                name: ".".to_string(),
+                children: vec![LegacyNode::File(LegacyFileNode { name: "a.txt".to_string(), last_modified: 2, hash })],
+            }),
+        };
+        let blank = decode(&bincode::serialize(&legacy(" ".repeat(64))).unwrap()).unwrap();
+        assert!(!blank.root.as_ref().unwrap().files()[0].has_hash());
+        assert!(decode(&bincode::serialize(&legacy("é".repeat(64))).unwrap()).is_err());
+        assert!(decode(&bincode::serialize(&legacy("g".repeat(64))).unwrap()).is_err());
+    }
+}
@@ -4,10 +4,10 @@ use tokio::{io};
 #[cfg(feature = "async")]
 use async_recursion::async_recursion;
 
-#[cfg(feature = "async")]
-use tokio::sync::mpsc;
 #[cfg(feature = "async")]
 use tokio::sync::mpsc::Sender;
+#[cfg(feature = "async")]
+use tokio::task::JoinHandle;
 
 #[cfg(not(feature = "async"))]
 use std::{io};
@@ -15,11 +15,12 @@ use std::{io};
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
+#[cfg(feature = "async")]
 use std::sync::atomic::AtomicUsize;
 use std::time::SystemTime;
-use log::log;
 use crate::data::FileData;
 use crate::hash::calculate_file_hash;
+use crate::rules::RuleSet;
 use crate::node::dir::DirectoryNode;
 use crate::node::file::FileNode;
 use crate::node::Node;
@@ -53,7 +54,7 @@ struct SafeFileNodePtr(*mut FileNode);
 unsafe impl Send for SafeFileNodePtr {}
 
 #[cfg(feature = "async")]
-pub async fn generate_file_data_from_path_with_progress<P: AsRef<Path>>(path: P, ignore: &Vec<String>, ptx: Sender<ProgressData>) -> io::Result<FileData> {
+pub async fn generate_file_data_from_path_with_progress<P: AsRef<Path>>(path: P, ignore: &[String], ptx: Sender<ProgressData>) -> io::Result<FileData> {
     let mut progress = ProgressData {
         total: 0,
         completed: 0,
@@ -63,8 +64,11 @@ pub async fn generate_file_data_from_path_with_progress<P: AsRef<Path>>(path: P,
     
     // calculate time running 
     let start = SystemTime::now();
-    let root = generate_root_from_path(path.as_ref(), "", ignore, total_counter.clone()).await?;
-    log::info!("Scan Done Elapsed time: {}s", SystemTime::now().duration_since(start).unwrap().as_secs());
+    let rules = scan_rules(path.as_ref(), ignore)?;
+    let mut root = generate_root_from_path(path.as_ref(), "", &rules, rules.includes_root(), total_counter.clone()).await?;
+    log::info!("Scan Done Elapsed time: {}s", start.elapsed().unwrap_or_default().as_secs());
+    let mut tasks = Vec::new();
+    generate_file_hash_for_node(path.as_ref(), &mut root, done_counter.clone(), &mut tasks).await;
     loop {
         progress.total = total_counter.load(std::sync::atomic::Ordering::SeqCst);
         progress.completed = done_counter.load(std::sync::atomic::Ordering::SeqCst);
@@ -72,21 +76,34 @@ pub async fn generate_file_data_from_path_with_progress<P: AsRef<Path>>(path: P,
             break;
         }
         
-        ptx.send(progress.clone()).await.unwrap();
+        // nobody is listening to the progress any more
+        if ptx.send(progress.clone()).await.is_err() {
+            break;
+        }
         tokio::time::sleep(std::time::Duration::from_millis(10)).await;
     }
-    log::info!("Async Elapsed time: {}s", SystemTime::now().duration_since(start).unwrap().as_secs());
+    log::info!("Async Elapsed time: {}s", start.elapsed().unwrap_or_default().as_secs());
+    for task in tasks {
+        task.await.map_err(io::Error::other)??;
+    }
 
     let data = FileData::new(
-        path.as_ref().to_str().unwrap().to_string(),
+        utf8(path.as_ref())?.to_string(),
         0,
         root,
     );
-    return Ok(data);
+    Ok(data)
+}
+
+#[cfg(feature = "async")]
+pub async fn generate_file_data_from_path<P: AsRef<Path>>(path: P, ignore: &[String]) -> io::Result<FileData> {
+    let rules = scan_rules(path.as_ref(), ignore)?;
+    generate_file_data_with_rules(path, &rules).await
 }
 
+/// Scans `path`, keeping the paths `rules` keep. The [`IGNORE_FILE`](crate::rules::IGNORE_FILE) is not read.
 #[cfg(feature = "async")]
-pub async fn generate_file_data_from_path<P: AsRef<Path>>(path: P, ignore: &Vec<String>) -> io::Result<FileData> {
+pub async fn generate_file_data_with_rules<P: AsRef<Path>>(path: P, rules: &RuleSet) -> io::Result<FileData> {
     let mut progress = ProgressData {
         total: 0,
         completed: 0,
@@ -96,9 +113,10 @@ pub async fn generate_file_data_from_path<P: AsRef<Path>>(path: P, ignore: &Vec<
 
     // calculate time running 
     let start = SystemTime::now();
-    let mut root = generate_root_from_path(path.as_ref(), "", ignore, total_counter.clone()).await?;
-    log::info!("Scan Done Elapsed time: {}s", SystemTime::now().duration_since(start).unwrap().as_secs());
-    generate_file_hash_for_node(&mut root, done_counter.clone()).await;
+    let mut root = generate_root_from_path(path.as_ref(), "", rules, rules.includes_root(), total_counter.clone()).await?;
+    log::info!("Scan Done Elapsed time: {}s", start.elapsed().unwrap_or_default().as_secs());
+    let mut tasks = Vec::new();
+    generate_file_hash_for_node(path.as_ref(), &mut root, done_counter.clone(), &mut tasks).await;
     // let root = 
     loop {
         progress.total = total_counter.load(std::sync::atomic::Ordering::SeqCst);
@@ -110,79 +128,126 @@ pub async fn generate_file_data_from_path<P: AsRef<Path>>(path: P, ignore: &Vec<
         log::info!("Progress: {}/{}", progress.completed, progress.total);
         tokio::time::sleep(std::time::Duration::from_millis(10)).await;
     }
-    log::info!("Async Elapsed time: {}s", SystemTime::now().duration_since(start).unwrap().as_secs());
+    log::info!("Async Elapsed time: {}s", start.elapsed().unwrap_or_default().as_secs());
+    for task in tasks {
+        task.await.map_err(io::Error::other)??;
+    }
 
     let data = FileData::new(
-        path.as_ref().to_str().unwrap().to_string(),
+        utf8(path.as_ref())?.to_string(),
         0,
         root,
     );
-    return Ok(data);
+    Ok(data)
 }
 
+/// Hashes every file under `node` in its own task, pushed onto `tasks`. Node paths are relative,
+/// so files are read under `root`, the scanned directory. `done` counts the files finished,
+/// whether or not hashing them succeeded.
 #[cfg(feature = "async")]
 #[async_recursion]
-pub async fn generate_file_hash_for_node(node: &mut DirectoryNode, done: Arc<AtomicUsize>) {
+pub async fn generate_file_hash_for_node(root: &Path, node: &mut DirectoryNode, done: Arc<AtomicUsize>, tasks: &mut Vec<JoinHandle<io::Result<()>>>) {
     for child in node.children.iter_mut() {
         match child {
             Node::File(file) => {
                 let ptr = SafeFileNodePtr(file as *mut FileNode);
-                let path = file.get_path();
+                let path = root.join(file.get_path());
                 let counter_clone = done.clone();
-                tokio::spawn(async move {
-                    let hash = calculate_file_hash(&path).await.unwrap();
+                tasks.push(tokio::spawn(async move {
+                    let result = calculate_file_hash(&path).await;
                     let p = ptr;
                     // log::debug!("Calculated hash for: {}", path.to_str().unwrap_or_default());
                     // tokio::time::sleep(std::time::Duration::from_millis(10));
-                    unsafe {
-                        (*p.0).set_hash(hash);
+                    if let Ok(hash) = &result {
+                        unsafe {
+                            (*p.0).set_hash(hash.clone());
+                        }
                     }
                     // log::debug!("Setting hash for: {}", path.to_str().unwrap_or_default());
                     counter_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                });
+                    result.map(|_| ()).map_err(io::Error::from)
+                }));
             },
             Node::Directory(dir) => {
-                generate_file_hash_for_node(dir, done.clone()).await;
+                generate_file_hash_for_node(root, dir, done.clone(), tasks).await;
             }
         }
     }
 }
 
 #[cfg(not(feature = "async"))]
-pub fn generate_file_data_from_path<P: AsRef<Path>>(path: P, ignore: &Vec<String>) -> io::Result<FileData> {
+pub fn generate_file_data_from_path<P: AsRef<Path>>(path: P, ignore: &[String]) -> io::Result<FileData> {
+    let rules = scan_rules(path.as_ref(), ignore)?;
+    generate_file_data_with_rules(path, &rules)
+}
+
+/// Scans `path`, keeping the paths `rules` keep. The [`IGNORE_FILE`](crate::rules::IGNORE_FILE) is not read.
+#[cfg(not(feature = "async"))]
+pub fn generate_file_data_with_rules<P: AsRef<Path>>(path: P, rules: &RuleSet) -> io::Result<FileData> {
     // calculate time running 
     let start = SystemTime::now();
-    let root = generate_root_from_path(path.as_ref(), "", ignore)?;
-    let elapsed = SystemTime::now().duration_since(start).unwrap().as_secs();
+    let root = generate_root_from_path(path.as_ref(), "", rules, rules.includes_root())?;
+    let elapsed = start.elapsed().unwrap_or_default().as_secs();
     log::info!("Elapsed time: {}s", elapsed);
 
     let data = FileData::new(
-        path.as_ref().to_str().unwrap().to_string(),
+        utf8(path.as_ref())?.to_string(),
         0,
         root,
     );
-    return Ok(data);
+    Ok(data)
 }
 
-fn join_path(path: &str, name: &str) -> String {
+/// The patterns of the [`IGNORE_FILE`](crate::rules::IGNORE_FILE) at `root`, then `ignore` as
+/// exclude patterns, so the latter can override the file.
+pub fn scan_rules<P: AsRef<Path>>(root: P, ignore: &[String]) -> io::Result<RuleSet> {
+    let mut rules = RuleSet::load(root)?;
+    for pattern in ignore {
+        rules.exclude(pattern);
+    }
+    Ok(rules)
+}
+
+/// Turns a scan path such as `./sub/a.txt` into the `sub/a.txt` form rules match.
+fn rule_key(path: &str) -> String {
+    path.strip_prefix('.').unwrap_or(path).trim_start_matches(std::path::MAIN_SEPARATOR).replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+/// Paths are kept as strings in a [`FileData`], so a scanned path must be valid UTF-8.
+fn utf8(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Path is not valid UTF-8: {}", path.display())))
+}
+
+pub(crate) fn file_name(path: &Path) -> io::Result<String> {
+    match path.file_name() {
+        Some(name) => Ok(utf8(Path::new(name))?.to_string()),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Path has no file name: {}", path.display()))),
+    }
+}
+
+pub(crate) fn join_path(path: &str, name: &str) -> String {
     if path.is_empty() {
         return name.to_string();
     }
-    return format!("{}{}{}", path, std::path::MAIN_SEPARATOR, name);
+    format!("{}{}{}", path, std::path::MAIN_SEPARATOR, name)
 }
 
+/// Scans the directory at `path`. `included` tells whether the entries no rule matches are kept.
 #[cfg(feature = "async")]
 #[async_recursion]
-pub async fn generate_root_from_path<P: AsRef<Path> + std::marker::Send>(path: P, relative_path: &str, ignore: &Vec<String>, total: Arc<AtomicUsize>) -> io::Result<DirectoryNode> {
+pub async fn generate_root_from_path<P>(path: P, relative_path: &str, rules: &RuleSet, included: bool, total: Arc<AtomicUsize>) -> io::Result<DirectoryNode>
+where
+    P: AsRef<Path> + Send,
+{
     log::debug!("Generate from: {}", path.as_ref().to_str().unwrap_or_default());
-    let mut data = if relative_path == "" {
+    let mut data = if relative_path.is_empty() {
         DirectoryNode::new(
             ".".to_string(),
             None
         )
     } else {
         DirectoryNode::new(
-            path.as_ref().file_name().unwrap().to_str().unwrap().to_string(),
+            file_name(path.as_ref())?,
             Some(Arc::from(relative_path)),
         )
     };
@@ -190,84 +255,123 @@ pub async fn generate_root_from_path<P: AsRef<Path> + std::marker::Send>(path: P
     let rp = Arc::from(join_path(relative_path, &data.name));
 
     // initialize with capacity
-    let count = fs::read_dir(&path).unwrap().count();
+    let count = fs::read_dir(&path)?.count();
     data.with_capacity(count);
 
-    let paths = fs::read_dir(path).unwrap();
+    let paths = fs::read_dir(path)?;
     for path in paths {
-        let path = path.unwrap().path();
-        let path_str = path.to_str().unwrap();
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
-        if path.is_dir() {
-            let rp_folder = join_path(&rp, &name);
-            let should_ignore = ignore.iter().any(|i| {
-                rp_folder.starts_with(i) || rp_folder.starts_with(&format!(".{}{}", std::path::MAIN_SEPARATOR, i))
-            });
-            if should_ignore {
+        let path = path?.path();
+        let path_str = utf8(&path)?;
+        let name = file_name(&path)?;
+        let is_dir = path.is_dir();
+        let included = match rules.matches(&rule_key(&join_path(&rp, &name)), is_dir) {
+            Some(false) => {
+                log::debug!("Ignoring: {}", path_str);
                 continue;
             }
-            data.add_child(Node::Directory(generate_root_from_path(path_str, &rp, ignore, total.clone()).await?));
-        } else {
+            Some(true) => true,
+            None => included,
+        };
+        if is_dir {
+            let dir = generate_root_from_path(path_str, &rp, rules, included, total.clone()).await?;
+            // a directory only entered for what it might include is dropped if it has nothing
+            if included || !dir.children.is_empty() {
+                data.add_child(Node::Directory(dir));
+            }
+        } else if included {
             let metadata = fs::metadata(&path)?;
-            let last_modified = metadata.modified()?.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-            let mut file_data = FileNode::new(
+            let last_modified = metadata.modified()?.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+            let file_data = FileNode::new(
                 rp.clone(),
                 name,
                 last_modified,
+                metadata.len(),
             );
 
             total.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
             data.add_child(Node::File(file_data));
         }
     }
-    return Ok(data);
+    Ok(data)
 }
 
 
+/// Scans the directory at `path`. `included` tells whether the entries no rule matches are kept.
 #[cfg(not(feature = "async"))]
-pub fn generate_root_from_path<P: AsRef<Path>>(path: P, relative_path: &str, ignore: &Vec<String>) -> io::Result<DirectoryNode> {
+pub fn generate_root_from_path<P: AsRef<Path>>(path: P, relative_path: &str, rules: &RuleSet, included: bool) -> io::Result<DirectoryNode> {
     log::debug!("Generate from: {}", path.as_ref().to_str().unwrap_or_default());
-    let mut data = if relative_path == "" {
+    let mut data = if relative_path.is_empty() {
         DirectoryNode::new(
             ".".to_string(),
             None
         )
     } else {
         DirectoryNode::new(
-            path.as_ref().file_name().unwrap().to_str().unwrap().to_string(),
+            file_name(path.as_ref())?,
             Some(Arc::from(relative_path)),
         )
     };
 
     let rp = Arc::from(join_path(relative_path, &data.name));
 
-    let paths = fs::read_dir(path).unwrap();
+    let paths = fs::read_dir(path)?;
     for path in paths {
-        let path = path.unwrap().path();
-        let path_str = path.to_str().unwrap();
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
-        if path.is_dir() {
-            let rp_folder = join_path(&rp, &name);
-            let should_ignore = ignore.iter().any(|i| {
-                rp_folder.starts_with(i) || rp_folder.starts_with(&format!(".{}{}", std::path::MAIN_SEPARATOR, i))
-            });
-            if should_ignore {
+        let path = path?.path();
+        let path_str = utf8(&path)?;
+        let name = file_name(&path)?;
+        let is_dir = path.is_dir();
+        let included = match rules.matches(&rule_key(&join_path(&rp, &name)), is_dir) {
+            Some(false) => {
+                log::debug!("Ignoring: {}", path_str);
                 continue;
             }
-            data.add_child(Node::Directory(generate_root_from_path(path_str, &rp, ignore)?));
-        } else {
+            Some(true) => true,
+            None => included,
+        };
+        if is_dir {
+            let dir = generate_root_from_path(path_str, &rp, rules, included)?;
+            // a directory only entered for what it might include is dropped if it has nothing
+            if included || !dir.children.is_empty() {
+                data.add_child(Node::Directory(dir));
+            }
+        } else if included {
             let metadata = fs::metadata(&path)?;
-            let last_modified = metadata.modified()?.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+            let last_modified = metadata.modified()?.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
             let mut file_data = FileNode::new(
                 rp.clone(),
                 name,
                 last_modified,
+                metadata.len(),
             );
-            let hash = calculate_file_hash(&path).unwrap();
+            let hash = calculate_file_hash(&path)?;
             file_data.set_hash(hash);
             data.add_child(Node::File(file_data));
         }
     }
 
-    return Ok(data);
-}
\ No newline at end of file
+    Ok(data)
+}
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use std::fs;
+    use crate::fs::generate_file_data_from_path;
+    use crate::hash::calculate_file_hash;
+    use crate::node::Node;
+
+    #[tokio::test]
+    async fn test_async_scan_outside_working_dir() {
+        let dir = std::env::temp_dir().join(format!("api_release_async_scan_{}", std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("a.txt"), "a").unwrap();
+
+        let data = generate_file_data_from_path(&dir, &[]).await.unwrap();
+        let Some(Node::Directory(sub)) = data.root.as_ref().unwrap().children.first() else { panic!("sub was not scanned") };
+        let Some(Node::File(file)) = sub.children.first() else { panic!("a.txt was not scanned") };
+        assert_eq!(file.get_hash(), calculate_file_hash(dir.join("sub").join("a.txt")).await.unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
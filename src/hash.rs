@@ -1,9 +1,7 @@
 use std::path::Path;
 use sha2::{Sha256, Digest};
 
-use thiserror::Error;
-
-pub type Result<T> = std::result::Result<T, Error>;
+use crate::error::Result;
 
 #[cfg(feature = "async")]
 use tokio::fs::File;
@@ -17,10 +15,24 @@ use tokio::io::AsyncReadExt;
 #[cfg(not(feature = "async"))]
 use std::io::Read;
 
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+pub fn calculate_hash(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn copy_with_hash<R: std::io::Read, W: std::io::Write>(rd: &mut R, wr: &mut W) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 1024];
+    loop {
+        let n = rd.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        wr.write_all(&buffer[..n])?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 #[cfg(feature = "async")]
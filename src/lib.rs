@@ -1,4 +1,21 @@
+pub mod archive;
+pub mod bundle;
+pub mod chunk;
+pub mod config;
 pub mod data;
+pub mod delta;
+pub mod error;
 pub mod fs;
+mod format;
 mod hash;
-pub mod node;
\ No newline at end of file
+pub mod manifest;
+pub mod node;
+pub mod patch;
+pub mod repair;
+pub mod report;
+pub mod repository;
+pub mod rules;
+pub mod signature;
+pub mod store;
+pub mod transaction;
+pub mod verify;
@@ -0,0 +1,1067 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use api_release::archive::{apply_archive_with_options, write_archive, ArchiveFormat};
+use api_release::bundle::{PatchBundle, PatchManifest};
+use api_release::chunk;
+use api_release::config::{OutputFormat, ProjectConfig, TargetConfig, CONFIG_FILE};
+use api_release::data::{FileData, ReleaseMetadata, TextFormat};
+use api_release::error::{Error, Result};
+use api_release::fs::{generate_file_data_from_path, generate_file_data_with_rules};
+use api_release::manifest::{entries_from_text, entries_to_text};
+use api_release::node::diff::{DiffSummary, FileDiff};
+use api_release::node::dir::DirectoryNode;
+use api_release::patch::{apply_patch, generate_patch, ApplyOptions};
+use api_release::repair::{repair, RepairSource};
+use api_release::report::{write_diffs, DiffFormat, DiffRecord};
+use api_release::repository::Repository;
+use api_release::rules::RuleSet;
+use api_release::signature::InstallSignature;
+use api_release::store::ObjectStore;
+use api_release::transaction;
+use api_release::verify::{verify, VerifyReport};
+use semver::Version;
+
+/// Exit code when `diff` or `verify` find differences, or `repair` leaves files broken.
+const DIFFERENT: u8 = 1;
+/// Exit code on errors.
+const FAILURE: u8 = 2;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Prints only errors, leaving the exit code to tell the result
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Logs progress, and details when repeated
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Scans a path and generates a file data
+    Scan {
+        /// path to scan
+        path: PathBuf,
+
+        /// File data to write, out.bin.gz by default
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+
+        #[command(flatten)]
+        rules: RuleArgs,
+
+        /// Splits files of at least this many bytes into content-defined chunks
+        #[arg(long, value_name = "BYTES")]
+        chunk: Option<u64>,
+
+        #[command(flatten)]
+        metadata: MetadataArgs,
+    },
+    /// Compares a path with a source file, exiting with 1 if they differ
+    Diff {
+        /// path to operate on
+        path: PathBuf,
+
+        /// source file
+        source: PathBuf,
+
+        /// Layout of the differences printed on stdout
+        #[arg(long, value_enum, default_value_t = DiffOutput::Text)]
+        format: DiffOutput,
+
+        /// Prints git-style status letters and paths, same as --format name-status
+        #[arg(long, conflicts_with = "format")]
+        name_status: bool,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+
+        #[command(flatten)]
+        rules: RuleArgs,
+
+        /// Splits files of at least this many bytes into content-defined chunks
+        #[arg(long, value_name = "BYTES")]
+        chunk: Option<u64>,
+    },
+    /// Generates a patch from a path and a source file
+    Patch {
+        /// path to operate on
+        path: PathBuf,
+
+        /// source file
+        source: PathBuf,
+
+        /// Patch folder to write, ./patch by default
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+
+        #[command(flatten)]
+        rules: RuleArgs,
+
+        /// Splits files of at least this many bytes into content-defined chunks
+        #[arg(long, value_name = "BYTES")]
+        chunk: Option<u64>,
+
+        /// Writes a single patch file instead of a patch folder, as an archive for .tar.gz, .tgz or .zip
+        #[arg(short, long, value_name = "FILE")]
+        bundle: Option<PathBuf>,
+
+        /// Installed tree of the source file, used to store changed files as deltas in the bundle
+        #[arg(long, value_name = "PATH", requires = "bundle")]
+        base: Option<PathBuf>,
+    },
+    /// Generates a release from a path and a source file
+    Release {
+        /// path to operate on
+        path: PathBuf,
+
+        /// source file
+        source: PathBuf,
+
+        /// Patch folder to write, ./release by default
+        #[arg(short='o', long, value_name = "PATH")]
+        output_path: Option<PathBuf>,
+
+        /// File data of the new release, out.bin.gz by default
+        #[arg(short='f', long, value_name = "PATH")]
+        output_file: Option<PathBuf>,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+
+        #[command(flatten)]
+        rules: RuleArgs,
+
+        /// Splits files of at least this many bytes into content-defined chunks
+        #[arg(long, value_name = "BYTES")]
+        chunk: Option<u64>,
+
+        /// Writes a single patch file instead of a patch folder, as an archive for .tar.gz, .tgz or .zip
+        #[arg(short, long, value_name = "FILE")]
+        bundle: Option<PathBuf>,
+
+        /// Installed tree of the source file, used to store changed files as deltas in the bundle
+        #[arg(long, value_name = "PATH", requires = "bundle")]
+        base: Option<PathBuf>,
+
+        /// Also adds the release to this repository as its next version
+        #[arg(long, value_name = "PATH")]
+        repository: Option<PathBuf>,
+
+        /// Points this channel of the repository at the new version
+        #[arg(long, value_name = "NAME", requires = "repository")]
+        channel: Option<String>,
+
+        #[command(flatten)]
+        metadata: MetadataArgs,
+    },
+    /// Applies a patch folder or bundle to an installed path
+    Apply {
+        /// patch folder or bundle generated by the patch or release command
+        patch: PathBuf,
+
+        /// installed path to update
+        path: PathBuf,
+
+        /// file data of the installed path, required for a patch folder; a bundle is only applied
+        /// if it upgrades from the version of this file data
+        source: Option<PathBuf>,
+
+        /// file data of the release the patch was generated for, required for a patch folder
+        target: Option<PathBuf>,
+
+        /// Hardlinks files with identical content instead of copying them
+        #[arg(long)]
+        hardlink: bool,
+    },
+    /// Checks an installed path against a file data, exiting with 1 on mismatch
+    Verify {
+        /// installed path to check
+        path: PathBuf,
+
+        /// file data the path should match
+        source: PathBuf,
+    },
+    /// Restores missing or corrupt files of an installed path from a release, exiting with 1 if some are left
+    Repair {
+        /// installed path to repair
+        path: PathBuf,
+
+        /// file data the path should match
+        source: PathBuf,
+
+        /// release folder or patch bundle to take the original files from
+        release: PathBuf,
+    },
+    /// Finishes or reverts an apply that was interrupted
+    Recover {
+        /// installed path the patch was applied to
+        path: PathBuf,
+
+        /// Restores the previous state instead of finishing the apply
+        #[arg(short, long)]
+        revert: bool,
+    },
+    /// Computes block signatures of an installed path to build a patch against
+    Signature {
+        /// installed path to sign
+        path: PathBuf,
+
+        /// signature file
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+    },
+    /// Generates a patch bundle from a path against the signatures of an installed path
+    Delta {
+        /// path to operate on
+        path: PathBuf,
+
+        /// signature file generated by the signature command
+        signature: PathBuf,
+
+        /// patch bundle file
+        bundle: PathBuf,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+    },
+    /// Manages releases kept in an object store
+    Store {
+        /// object store folder
+        store: PathBuf,
+
+        #[command(subcommand)]
+        command: StoreCommands,
+    },
+    /// Writes a file data as JSON or TOML
+    Export {
+        /// file data to export
+        source: PathBuf,
+
+        /// text file to write
+        output: PathBuf,
+
+        /// Text format, guessed from the output extension by default
+        #[arg(short, long, value_enum)]
+        format: Option<Format>,
+
+        /// Writes a sorted list of files instead of the tree
+        #[arg(long)]
+        flat: bool,
+    },
+    /// Reads a file data back from JSON or TOML
+    Import {
+        /// text file to read
+        input: PathBuf,
+
+        /// file data to write
+        output: PathBuf,
+
+        /// Text format, guessed from the input extension by default
+        #[arg(short, long, value_enum)]
+        format: Option<Format>,
+
+        /// Reads a list of files, such as one written by a build system, instead of a tree
+        #[arg(long)]
+        flat: bool,
+    },
+    /// Prints the release described by a file data
+    Info {
+        /// file data to describe
+        source: PathBuf,
+    },
+    /// Manages a versioned release repository
+    Repo {
+        /// repository folder
+        repository: PathBuf,
+
+        #[command(subcommand)]
+        command: RepoCommands,
+    },
+    /// Builds the release of a target defined in the project configuration
+    Build {
+        /// target name
+        target: String,
+
+        /// Project configuration file
+        #[arg(short, long, value_name = "FILE", default_value = CONFIG_FILE)]
+        config: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Toml,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DiffOutput {
+    /// One `A: path` line per difference
+    Text,
+    /// A JSON array with sizes and hashes
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    /// Comma-separated values with a header line
+    Csv,
+    /// Tab-separated status letters and paths
+    NameStatus,
+}
+
+/// Release metadata stored in the file data
+#[derive(Args)]
+struct MetadataArgs {
+    /// Semantic version of the release
+    #[arg(long, value_name = "VERSION")]
+    semver: Option<Version>,
+
+    /// Release notes
+    #[arg(long, value_name = "TEXT")]
+    notes: Option<String>,
+
+    /// Adds a label to the release
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_label)]
+    label: Vec<(String, String)>,
+
+    /// Oldest updater version able to install the release
+    #[arg(long, value_name = "VERSION")]
+    min_updater_version: Option<Version>,
+
+    /// Identifier of the build the release comes from
+    #[arg(long, value_name = "ID")]
+    build_id: Option<String>,
+}
+
+impl MetadataArgs {
+    fn to_metadata(&self) -> ReleaseMetadata {
+        ReleaseMetadata {
+            semver: self.semver.clone(),
+            notes: self.notes.clone(),
+            labels: self.label.iter().cloned().collect(),
+            min_updater_version: self.min_updater_version.clone(),
+            build_id: self.build_id.clone(),
+        }
+    }
+}
+
+/// Ordered rules picking what a scan keeps, applied after the path's .releaseignore
+#[derive(Args)]
+struct RuleArgs {
+    /// Gitignore-style pattern to scan, so only the included paths are kept; repeat for several
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+
+    /// File of patterns, one per line, prefixed with + to include and - to exclude, applied in order
+    #[arg(long, value_name = "FILE")]
+    rules: Option<PathBuf>,
+}
+
+impl RuleArgs {
+    /// The .releaseignore of `path`, then the rules file, then the include and the ignore patterns.
+    fn to_rules(&self, path: &Path, ignore: Option<&[String]>) -> io::Result<RuleSet> {
+        let mut rules = RuleSet::load(path)?;
+        if let Some(file) = &self.rules {
+            rules.extend(RuleSet::from_file(file)?);
+        }
+        for pattern in &self.include {
+            rules.include(pattern);
+        }
+        for pattern in ignore.unwrap_or_default() {
+            rules.exclude(pattern);
+        }
+        Ok(rules)
+    }
+}
+
+fn parse_label(label: &str) -> std::result::Result<(String, String), String> {
+    match label.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {}", label)),
+    }
+}
+
+#[derive(Subcommand)]
+enum RepoCommands {
+    /// Scans a path and adds it to the repository as the next version
+    Add {
+        /// path to operate on
+        path: PathBuf,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+
+        /// Splits files of at least this many bytes into content-defined chunks
+        #[arg(long, value_name = "BYTES")]
+        chunk: Option<u64>,
+
+        #[command(flatten)]
+        metadata: MetadataArgs,
+    },
+    /// Lists the stored versions and patches
+    List,
+    /// Prints the stored patches upgrading a version to the latest one, without building any
+    Plan {
+        /// version to upgrade from
+        from: u64,
+
+        /// Upgrades to the version of this channel instead of the latest one
+        #[arg(short, long, value_name = "NAME")]
+        channel: Option<String>,
+    },
+    /// Upgrades an installed path to the latest version
+    Update {
+        /// installed path to update
+        path: PathBuf,
+
+        /// version of the installed path
+        from: u64,
+
+        /// Upgrades to the version of this channel instead of the latest one
+        #[arg(short, long, value_name = "NAME")]
+        channel: Option<String>,
+    },
+    /// Prints the version of a channel, or points it at a version
+    Channel {
+        /// channel name, such as stable, beta or nightly
+        name: String,
+
+        /// version to point the channel at
+        version: Option<u64>,
+    },
+    /// Points a channel at the version of another channel
+    Promote {
+        /// channel to take the version from
+        from: String,
+
+        /// channel to update
+        to: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreCommands {
+    /// Scans a path and adds it to the store as a release
+    Add {
+        /// release name
+        name: String,
+
+        /// path to operate on
+        path: PathBuf,
+
+        /// Gitignore-style patterns to leave out, applied after the path's .releaseignore
+        #[arg(short, long, value_name = "LIST", value_delimiter = ',')]
+        ignore: Option<Vec<String>>,
+
+        /// Splits files of at least this many bytes into content-defined chunks
+        #[arg(long, value_name = "BYTES")]
+        chunk: Option<u64>,
+    },
+    /// Lists the stored releases
+    List,
+    /// Writes a patch bundle between two stored releases
+    Patch {
+        /// release to upgrade from
+        from: String,
+
+        /// release to upgrade to
+        to: String,
+
+        /// patch bundle file
+        output: PathBuf,
+    },
+    /// Writes a stored release into a path
+    Checkout {
+        /// release name
+        name: String,
+
+        /// path to write to
+        path: PathBuf,
+    },
+}
+
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, _) => LevelFilter::Debug,
+    };
+    simple_logger::SimpleLogger::new().with_level(level).init().expect("Logger already set");
+    log::info!("Starting up v{}", env!("CARGO_PKG_VERSION"));
+
+    match run(&cli.command, &Output { quiet: cli.quiet }) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(FAILURE)
+        },
+    }
+}
+
+/// Reports go to stdout and status lines to stderr, so stdout stays parseable. `--quiet` drops both.
+struct Output {
+    quiet: bool,
+}
+
+impl Output {
+    fn report<T: fmt::Display>(&self, line: T) {
+        if !self.quiet {
+            println!("{}", line);
+        }
+    }
+
+    fn status<T: fmt::Display>(&self, line: T) {
+        if !self.quiet {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Runs an async library call to completion; the sync build passes values through.
+#[cfg(feature = "async")]
+fn wait<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Runtime::new().expect("Cannot start runtime").block_on(future)
+}
+
+#[cfg(not(feature = "async"))]
+fn wait<T>(value: T) -> T {
+    value
+}
+
+fn run(command: &Commands, out: &Output) -> Result<ExitCode> {
+    match command {
+        Commands::Scan { path, output, ignore, rules, chunk, metadata } => {
+            check_dir(path)?;
+            let output = output.clone().unwrap_or_else(|| PathBuf::from("out.bin.gz"));
+            log::info!("Scanning {}", path.display());
+            let rules = rules.to_rules(path, ignore.as_deref())?;
+            let mut file_data = wait(generate_file_data_with_rules(path, &rules))?;
+            chunk_files(&mut file_data, path, *chunk)?;
+            file_data.metadata = metadata.to_metadata();
+            file_data.save(&output)?;
+            out.status(format_args!("Saved {} files to {}", file_count(&file_data), output.display()));
+        },
+        Commands::Diff { path, source, format, name_status, ignore, rules, chunk } => {
+            check_dir(path)?;
+            check_file(source)?;
+            log::info!("Compare {} with {}", path.display(), source.display());
+
+            let source_filedata = FileData::load(source)?;
+            let rules = rules.to_rules(path, ignore.as_deref())?;
+            let mut target_filedata = wait(generate_file_data_with_rules(path, &rules))?;
+            chunk_files(&mut target_filedata, path, *chunk)?;
+            let diffs = source_filedata.diff(&target_filedata)?;
+            let format = if *name_status { DiffOutput::NameStatus } else { *format };
+            print_diffs(&diffs, &source_filedata, &target_filedata, format, out)?;
+            out.status(format_args!("Patch is {}", DiffSummary::new(&diffs)));
+            log_changed_chunks(&source_filedata, &target_filedata);
+            if !diffs.is_empty() {
+                return Ok(ExitCode::from(DIFFERENT));
+            }
+        },
+        Commands::Patch { path, source, output, ignore, rules, chunk, bundle, base } => {
+            check_dir(path)?;
+            let output = output.clone().unwrap_or_else(|| PathBuf::from(".").join("patch"));
+            log::info!("Generate patch {} with {} to {}", path.display(), source.display(), output.display());
+
+            let source_filedata = load_source(source)?;
+            let rules = rules.to_rules(path, ignore.as_deref())?;
+            let mut target_filedata = wait(generate_file_data_with_rules(path, &rules))?;
+            chunk_files(&mut target_filedata, path, *chunk)?;
+            let diffs = source_filedata.diff(&target_filedata)?;
+            out.status(format_args!("Patch is {}", DiffSummary::new(&diffs)));
+
+            match bundle {
+                Some(bundle) => write_bundle(path, &source_filedata, &target_filedata, bundle, base.as_deref())?,
+                None => write_patch_folder(path, &output, &diffs)?,
+            }
+        },
+        Commands::Release { path, source, output_path, output_file, ignore, rules, chunk, bundle, base, repository, channel, metadata } => {
+            check_dir(path)?;
+            let output_path = output_path.clone().unwrap_or_else(|| PathBuf::from(".").join("release"));
+            let output_file = output_file.clone().unwrap_or_else(|| PathBuf::from("out.bin.gz"));
+            log::info!("Generate release {} with {} to {}", path.display(), source.display(), output_path.display());
+
+            let source_filedata = load_source(source)?;
+            let rules = rules.to_rules(path, ignore.as_deref())?;
+            let mut target_filedata = wait(generate_file_data_with_rules(path, &rules))?;
+            chunk_files(&mut target_filedata, path, *chunk)?;
+            target_filedata.metadata = metadata.to_metadata();
+            if let Some(repository) = repository {
+                target_filedata = publish(repository, channel.as_deref(), path, target_filedata, out)?;
+            }
+            let diffs = source_filedata.diff(&target_filedata)?;
+            out.status(format_args!("Patch is {}", DiffSummary::new(&diffs)));
+
+            match bundle {
+                Some(bundle) => write_bundle(path, &source_filedata, &target_filedata, bundle, base.as_deref())?,
+                None => write_patch_folder(path, &output_path, &diffs)?,
+            }
+            log::info!("Saving output to {}", output_file.display());
+            target_filedata.save(&output_file)?;
+        },
+        Commands::Apply { patch, path, source, target, hardlink } => {
+            run_apply(patch, path, source.as_deref(), target.as_deref(), *hardlink)?;
+        },
+        Commands::Recover { path, revert } => {
+            run_recover(path, *revert, out)?;
+        },
+        Commands::Verify { path, source } => {
+            check_dir(path)?;
+            check_file(source)?;
+            log::info!("Verify {} with {}", path.display(), source.display());
+
+            let source_filedata = FileData::load(source)?;
+            let report = wait(verify(path, &source_filedata))?;
+            if !print_verify_report(&report, out) {
+                return Ok(ExitCode::from(DIFFERENT));
+            }
+        },
+        Commands::Repair { path, source, release } => {
+            check_dir(path)?;
+            check_file(source)?;
+            check_exists(release)?;
+            log::info!("Repair {} from {}", path.display(), release.display());
+
+            let source_filedata = FileData::load(source)?;
+            let patch_bundle = if release.is_file() { Some(PatchBundle::load(release)?) } else { None };
+            let repair_source = match patch_bundle.as_ref() {
+                Some(patch_bundle) => RepairSource::Bundle(patch_bundle),
+                None => RepairSource::Directory(release),
+            };
+            let report = wait(repair(path, repair_source, &source_filedata))?;
+            for path in &report.repaired {
+                out.report(format_args!("repaired {}", path));
+            }
+            for path in &report.unavailable {
+                out.report(format_args!("unavailable {}", path));
+            }
+            out.status(format_args!("Repaired {} files", report.repaired.len()));
+            if !report.unavailable.is_empty() {
+                return Ok(ExitCode::from(DIFFERENT));
+            }
+        },
+        Commands::Signature { path, output, ignore } => {
+            check_dir(path)?;
+            let output = output.clone().unwrap_or_else(|| PathBuf::from("signature.bin.gz"));
+            log::info!("Signing {}", path.display());
+            let ignores = ignore.clone().unwrap_or_default();
+            let signature = wait(InstallSignature::scan(path, &ignores))?;
+            signature.save(&output)?;
+            out.status(format_args!("Saved signature to {}", output.display()));
+        },
+        Commands::Delta { path, signature, bundle, ignore } => {
+            check_dir(path)?;
+            check_file(signature)?;
+            log::info!("Generate patch {} against {} to {}", path.display(), signature.display(), bundle.display());
+            let install_signature = InstallSignature::load(signature)?;
+            let ignores = ignore.clone().unwrap_or_default();
+            let target_filedata = wait(generate_file_data_from_path(path, &ignores))?;
+            PatchBundle::create_for_install(path, &install_signature, &target_filedata)?.save(bundle)?;
+        },
+        Commands::Store { store, command } => {
+            run_store(store, command, out)?;
+        },
+        Commands::Export { source, output, format, flat } => {
+            check_file(source)?;
+            let format = text_format(output, *format)?;
+            log::info!("Exporting {} to {}", source.display(), output.display());
+            let file_data = FileData::load(source)?;
+            if *flat {
+                fs::write(output, entries_to_text(&file_data.to_entries(), format)?)?;
+            } else {
+                file_data.export(output, format)?;
+            }
+        },
+        Commands::Import { input, output, format, flat } => {
+            check_file(input)?;
+            let format = text_format(input, *format)?;
+            log::info!("Importing {} to {}", input.display(), output.display());
+            let file_data = if *flat {
+                let entries = entries_from_text(&fs::read_to_string(input)?, format)?;
+                FileData::from_entries(input.to_string_lossy().into_owned(), 0, entries)?
+            } else {
+                FileData::import(input, format)?
+            };
+            file_data.save(output)?;
+        },
+        Commands::Info { source } => {
+            check_file(source)?;
+            print_info(&FileData::load(source)?, out);
+        },
+        Commands::Build { target, config } => {
+            let target = ProjectConfig::load(config)?.target(target)?.clone();
+            check_dir(&target.path)?;
+            log::info!("Building {} to {}", target.path.display(), target.output.display());
+            let rules = target.rules()?;
+            let file_data = wait(generate_file_data_with_rules(&target.path, &rules))?;
+            build_target(&target, file_data, out)?;
+        },
+        Commands::Repo { repository, command } => {
+            run_repo(repository, command, out)?;
+        },
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_diffs(diffs: &[FileDiff], source: &FileData, target: &FileData, format: DiffOutput, out: &Output) -> Result<()> {
+    let format = match format {
+        DiffOutput::Text => {
+            for diff in diffs {
+                out.report(diff);
+            }
+            return Ok(());
+        },
+        DiffOutput::Json => DiffFormat::Json,
+        DiffOutput::Jsonl => DiffFormat::JsonLines,
+        DiffOutput::Csv => DiffFormat::Csv,
+        DiffOutput::NameStatus => DiffFormat::NameStatus,
+    };
+    if !out.quiet {
+        write_diffs(&DiffRecord::collect(diffs, source, target), format, io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+fn check_exists(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Err(Error::NotFound(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+fn check_dir(path: &Path) -> Result<()> {
+    check_exists(path)?;
+    if !path.is_dir() {
+        return Err(Error::InvalidInput(format!("{} is not a directory", path.display())));
+    }
+    Ok(())
+}
+
+fn check_file(path: &Path) -> Result<()> {
+    check_exists(path)?;
+    if !path.is_file() {
+        return Err(Error::InvalidInput(format!("{} is not a file", path.display())));
+    }
+    Ok(())
+}
+
+fn write_patch_folder(path: &Path, output: &Path, diffs: &[FileDiff]) -> Result<()> {
+    if output.exists() {
+        log::warn!("Patch folder already exist: {}", output.display());
+    }
+    fs::create_dir_all(output)?;
+    log::info!("Copying files...");
+    generate_patch(path, output, diffs)?;
+    Ok(())
+}
+
+fn write_bundle(path: &Path, source: &FileData, target: &FileData, bundle: &Path, base: Option<&Path>) -> Result<()> {
+    if let Some(format) = ArchiveFormat::from_path(bundle) {
+        if base.is_some() {
+            return Err(Error::InvalidInput("Deltas are only supported in bundle files, not archives".to_string()));
+        }
+        log::info!("Writing patch archive to {}", bundle.display());
+        write_archive(path, &PatchManifest::new(source, target)?, bundle, format)?;
+        return Ok(());
+    }
+    log::info!("Writing patch bundle to {}", bundle.display());
+    let patch_bundle = match base {
+        Some(base) => PatchBundle::create_with_delta(path, base, source, target)?,
+        None => PatchBundle::create(path, source, target)?,
+    };
+    patch_bundle.save(bundle)?;
+    Ok(())
+}
+
+fn run_apply(patch: &Path, path: &Path, source: Option<&Path>, target: Option<&Path>, hardlink: bool) -> Result<()> {
+    check_exists(patch)?;
+    check_dir(path)?;
+
+    log::info!("Apply patch {} to {}", patch.display(), path.display());
+    if let Some(format) = ArchiveFormat::from_path(patch).filter(|_| patch.is_file()) {
+        apply_archive_with_options(patch, path, format, &ApplyOptions { hardlink })?;
+        return Ok(());
+    }
+    if patch.is_file() {
+        let patch_bundle = PatchBundle::load(patch)?;
+        if let Some(source) = source {
+            let source_filedata = FileData::load(source)?;
+            if !patch_bundle.manifest.applies_to(&source_filedata) {
+                return Err(Error::InvalidInput(format!("Patch upgrades version {}, not version {}", patch_bundle.manifest.from_version, source_filedata.version)));
+            }
+        }
+        patch_bundle.apply_with_options(path, &ApplyOptions { hardlink })?;
+        return Ok(());
+    }
+
+    let (Some(source), Some(target)) = (source, target) else {
+        return Err(Error::InvalidInput("Source and target file data are required for a patch folder".to_string()));
+    };
+    let source_filedata = FileData::load(source)?;
+    let target_filedata = FileData::load(target)?;
+    let diffs = source_filedata.diff(&target_filedata)?;
+
+    apply_patch(patch, path, &diffs)?;
+    Ok(())
+}
+
+fn run_recover(path: &Path, revert: bool, out: &Output) -> Result<()> {
+    if !transaction::is_interrupted(path)? {
+        out.status(format_args!("Nothing to recover in {}", path.display()));
+        return Ok(());
+    }
+    if revert {
+        log::info!("Reverting interrupted apply in {}", path.display());
+        transaction::revert(path)?;
+    } else {
+        log::info!("Resuming interrupted apply in {}", path.display());
+        transaction::resume(path)?;
+    }
+    Ok(())
+}
+
+fn chunk_files(file_data: &mut FileData, path: &Path, min_size: Option<u64>) -> Result<()> {
+    if let Some(min_size) = min_size {
+        let chunked = chunk::add_chunks(file_data, path, min_size)?;
+        log::info!("Chunked {} files", chunked);
+    }
+    Ok(())
+}
+
+fn log_changed_chunks(source: &FileData, target: &FileData) {
+    let (Some(source_root), Some(target_root)) = (source.root.as_ref(), target.root.as_ref()) else {
+        return;
+    };
+    let old: HashMap<String, _> = source_root.files().into_iter().map(|file| (file.get_path(), file)).collect();
+    for file in target_root.files() {
+        let Some(old_file) = old.get(&file.get_path()) else {
+            continue;
+        };
+        if file.chunks.is_empty() || old_file.chunks.is_empty() || file.hash == old_file.hash {
+            continue;
+        }
+        let changed = chunk::changed_chunks(&old_file.chunks, &file.chunks);
+        let bytes: u64 = changed.iter().map(|c| c.len as u64).sum();
+        log::info!("{}: {} of {} chunks changed ({} bytes)", file.get_path(), changed.len(), file.chunks.len(), bytes);
+    }
+}
+
+/// Loads the file data of the previous release, or an empty one for a first release.
+fn load_source(source: &Path) -> Result<FileData> {
+    match FileData::load(source) {
+        Err(Error::NotFound(_)) => {
+            log::info!("No file data at {}, treating this as the first release", source.display());
+            Ok(FileData::new(source.to_string_lossy().into_owned(), 0, DirectoryNode::new(".".to_string(), None)))
+        },
+        result => result,
+    }
+}
+
+fn text_format(path: &Path, format: Option<Format>) -> Result<TextFormat> {
+    let format = match format {
+        Some(Format::Json) => Some(TextFormat::Json),
+        Some(Format::Toml) => Some(TextFormat::Toml),
+        None => TextFormat::from_path(path),
+    };
+    format.ok_or_else(|| Error::InvalidInput(format!("Cannot tell the format of {}, use --format", path.display())))
+}
+
+fn file_count(file_data: &FileData) -> usize {
+    file_data.root.as_ref().map_or(0, |root| root.files().len())
+}
+
+fn print_info(file_data: &FileData, out: &Output) {
+    out.report(format_args!("Path: {}", file_data.path));
+    out.report(format_args!("Release: {}", file_data.version));
+    out.report(format_args!("Time: {}", file_data.time));
+    out.report(format_args!("Files: {}", file_count(file_data)));
+    for line in file_data.metadata.to_string().lines() {
+        out.report(line);
+    }
+}
+
+/// Writes the patch from the target manifest to `target_filedata`, then replaces the manifest.
+fn build_target(target: &TargetConfig, mut target_filedata: FileData, out: &Output) -> Result<()> {
+    chunk_files(&mut target_filedata, &target.path, target.chunk)?;
+    let source_filedata = load_source(&target.manifest)?;
+    if let Some(repository) = &target.repository {
+        target_filedata = publish(repository, target.channel.as_deref(), &target.path, target_filedata, out)?;
+    }
+    let diffs = source_filedata.diff(&target_filedata)?;
+    out.status(format_args!("Patch is {}", DiffSummary::new(&diffs)));
+
+    log::info!("Writing patch to {}", target.output.display());
+    for file in [&target.output, &target.manifest] {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let archive = match target.format {
+        OutputFormat::Folder => {
+            write_patch_folder(&target.path, &target.output, &diffs)?;
+            None
+        },
+        OutputFormat::Bundle => {
+            PatchBundle::create(&target.path, &source_filedata, &target_filedata)?.save(&target.output)?;
+            None
+        },
+        OutputFormat::TarGz => Some(ArchiveFormat::TarGz),
+        #[cfg(feature = "zip")]
+        OutputFormat::Zip => Some(ArchiveFormat::Zip),
+    };
+    if let Some(format) = archive {
+        let manifest = PatchManifest::new(&source_filedata, &target_filedata)?;
+        write_archive(&target.path, &manifest, &target.output, format)?;
+    }
+
+    log::info!("Saving output to {}", target.manifest.display());
+    target_filedata.save(&target.manifest)?;
+    Ok(())
+}
+
+fn publish(repository: &Path, channel: Option<&str>, path: &Path, file_data: FileData, out: &Output) -> Result<FileData> {
+    let repository = Repository::open(repository)?;
+    let version = repository.add_release(path, file_data)?;
+    out.status(format_args!("Published version {}", version));
+    if let Some(channel) = channel {
+        repository.set_channel(channel, version)?;
+        out.status(format_args!("Channel {} now points at version {}", channel, version));
+    }
+    Ok(repository.manifest(version)?)
+}
+
+fn run_repo(repository_path: &Path, command: &RepoCommands, out: &Output) -> Result<()> {
+    let repository = Repository::open(repository_path)?;
+    match command {
+        RepoCommands::Add { path, ignore, chunk, metadata } => {
+            check_dir(path)?;
+            log::info!("Adding {} to {}", path.display(), repository_path.display());
+            let ignores = ignore.clone().unwrap_or_default();
+            let mut file_data = wait(generate_file_data_from_path(path, &ignores))?;
+            chunk_files(&mut file_data, path, *chunk)?;
+            file_data.metadata = metadata.to_metadata();
+            let version = repository.add_release(path, file_data)?;
+            out.status(format_args!("Added version {}", version));
+        },
+        RepoCommands::List => {
+            for version in repository.versions()? {
+                out.report(format_args!("version {}", version));
+            }
+            for (from, to) in repository.patches()? {
+                out.report(format_args!("patch {} -> {}", from, to));
+            }
+            for (name, version) in repository.channels()? {
+                out.report(format_args!("channel {} -> {}", name, version));
+            }
+        },
+        RepoCommands::Plan { from, channel } => {
+            let target = match channel {
+                Some(channel) => repository.channel(channel)?,
+                None => repository.latest()?,
+            };
+            let target = target.ok_or_else(|| Error::InvalidInput("No version to upgrade to".to_string()))?;
+            for patch in repository.update_path(*from, target)? {
+                out.report(patch.display());
+            }
+        },
+        RepoCommands::Update { path, from, channel } => {
+            check_dir(path)?;
+            let version = match channel {
+                Some(channel) => repository.update_channel(path, *from, channel)?,
+                None => repository.update(path, *from)?,
+            };
+            out.status(format_args!("Updated {} to version {}", path.display(), version));
+        },
+        RepoCommands::Channel { name, version } => match version {
+            Some(version) => repository.set_channel(name, *version)?,
+            None => match repository.channel(name)? {
+                Some(version) => out.report(version),
+                None => return Err(Error::InvalidInput(format!("No channel named {}", name))),
+            },
+        },
+        RepoCommands::Promote { from, to } => {
+            let version = repository.promote(from, to)?;
+            out.status(format_args!("Promoted version {} from {} to {}", version, from, to));
+        },
+    }
+    Ok(())
+}
+
+fn run_store(store: &Path, command: &StoreCommands, out: &Output) -> Result<()> {
+    let object_store = ObjectStore::open(store)?;
+    match command {
+        StoreCommands::Add { name, path, ignore, chunk } => {
+            check_dir(path)?;
+            log::info!("Adding {} to {} as {}", path.display(), store.display(), name);
+            let ignores = ignore.clone().unwrap_or_default();
+            let mut file_data = wait(generate_file_data_from_path(path, &ignores))?;
+            chunk_files(&mut file_data, path, *chunk)?;
+            let added = object_store.add_release(name, path, &file_data)?;
+            out.status(format_args!("Stored {} new blobs", added));
+        },
+        StoreCommands::List => {
+            for name in object_store.manifests()? {
+                out.report(name);
+            }
+        },
+        StoreCommands::Patch { from, to, output } => {
+            log::info!("Writing patch bundle from {} to {} to {}", from, to, output.display());
+            object_store.build_patch(from, to)?.save(output)?;
+        },
+        StoreCommands::Checkout { name, path } => {
+            log::info!("Checking out {} to {}", name, path.display());
+            object_store.checkout(name, path)?;
+        },
+    }
+    Ok(())
+}
+
+/// Prints every mismatch and returns whether there was none.
+fn print_verify_report(report: &VerifyReport, out: &Output) -> bool {
+    for path in &report.missing {
+        out.report(format_args!("missing {}", path));
+    }
+    for path in &report.extra {
+        out.report(format_args!("extra {}", path));
+    }
+    for path in &report.corrupt {
+        out.report(format_args!("corrupt {}", path));
+    }
+    if report.is_ok() {
+        out.status("All files match");
+    }
+    report.is_ok()
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::data::{FileData, TextFormat};
+use crate::error::{Error, Result};
+use crate::node::dir::DirectoryNode;
+use crate::node::file::{is_valid_hash, FileNode};
+use crate::node::Node;
+
+/// One file of a manifest, with its path relative to the release root using `/` separators.
+///
+/// Empty directories have no entry.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    /// Size in bytes, when known.
+    pub size: Option<u64>,
+    pub last_modified: u64,
+    /// SHA-256 of the content, empty when unknown.
+    pub hash: String,
+}
+
+/// Walks the files of a tree in tree order, see [`FileData::entries`].
+pub struct Entries<'a> {
+    stack: Vec<(String, std::slice::Iter<'a, Node>)>,
+}
+
+impl<'a> Entries<'a> {
+    pub fn new(root: Option<&'a DirectoryNode>) -> Self {
+        Entries {
+            stack: root.map(|root| (String::new(), root.children.iter())).into_iter().collect(),
+        }
+    }
+}
+
+impl Iterator for Entries<'_> {
+    type Item = ManifestEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (prefix, children) = self.stack.last_mut()?;
+            match children.next() {
+                None => {
+                    self.stack.pop();
+                }
+                Some(Node::File(file)) => {
+                    return Some(ManifestEntry {
+                        path: format!("{}{}", prefix, file.name),
+                        size: file.size,
+                        last_modified: file.last_modified,
+                        hash: if file.has_hash() { file.get_hash() } else { String::new() },
+                    });
+                }
+                Some(Node::Directory(dir)) => {
+                    let prefix = format!("{}{}/", prefix, dir.name);
+                    self.stack.push((prefix, dir.children.iter()));
+                }
+            }
+        }
+    }
+}
+
+impl FileData {
+    /// Iterates over every file of the tree, in tree order.
+    pub fn entries(&self) -> Entries<'_> {
+        Entries::new(self.root.as_ref())
+    }
+
+    /// Lists every file of the tree, sorted by path.
+    pub fn to_entries(&self) -> Vec<ManifestEntry> {
+        let mut entries: Vec<_> = self.entries().collect();
+        entries.sort();
+        entries
+    }
+
+    /// Builds a file data from a flat list of files, as produced by a build system, without
+    /// reading the filesystem.
+    pub fn from_entries<I: IntoIterator<Item = ManifestEntry>>(path: String, version: u64, entries: I) -> Result<Self> {
+        Ok(FileData::new(path, version, DirectoryNode::from_entries(entries)?))
+    }
+}
+
+impl DirectoryNode {
+    /// Builds the root of a tree holding `entries`, creating their parent directories.
+    pub fn from_entries<I: IntoIterator<Item = ManifestEntry>>(entries: I) -> Result<Self> {
+        let mut root = DirectoryNode::new(".".to_string(), None);
+        for entry in entries {
+            let invalid = |reason: &str| Error::InvalidEntry(format!("{}: {}", entry.path, reason));
+            let parts: Vec<&str> = entry.path.split(['/', '\\']).collect();
+            if parts.iter().any(|part| part.is_empty() || *part == "." || *part == "..") {
+                return Err(invalid("not a normalized relative path"));
+            }
+            if !entry.hash.is_empty() && !is_valid_hash(&entry.hash) {
+                return Err(invalid("hash is not a SHA-256 hex digest"));
+            }
+
+            let (name, dirs) = parts.split_last().unwrap();
+            let mut dir = &mut root;
+            for part in dirs {
+                dir = child_directory(dir, part).ok_or_else(|| invalid("a parent is a file"))?;
+            }
+            let mut file = FileNode::new(Arc::from(""), name.to_string(), entry.last_modified, 0);
+            file.size = entry.size;
+            if !entry.hash.is_empty() {
+                file.set_hash(entry.hash.to_lowercase());
+            }
+            if dir.children.iter().any(|child| child.name() == name) {
+                return Err(invalid("duplicate path"));
+            }
+            dir.add_child(Node::File(file));
+        }
+        root.restore_path(None);
+        Ok(root)
+    }
+}
+
+/// Returns the child directory `name` of `dir`, creating it if needed, or `None` if it is a file.
+fn child_directory<'a>(dir: &'a mut DirectoryNode, name: &str) -> Option<&'a mut DirectoryNode> {
+    if !dir.children.iter().any(|child| child.name() == name) {
+        dir.add_child(Node::Directory(DirectoryNode::new(name.to_string(), None)));
+    }
+    dir.children.iter_mut().find_map(|child| match child {
+        Node::Directory(child) if child.name == name => Some(child),
+        _ => None,
+    })
+}
+
+#[derive(Deserialize, Serialize)]
+struct EntryList {
+    entries: Vec<ManifestEntry>,
+}
+
+pub fn entries_to_text(entries: &[ManifestEntry], format: TextFormat) -> Result<String> {
+    let list = EntryList { entries: entries.to_vec() };
+    match format {
+        TextFormat::Json => serde_json::to_string_pretty(&list).map_err(|e| Error::Encode(e.to_string())),
+        TextFormat::Toml => toml::to_string_pretty(&list).map_err(|e| Error::Encode(e.to_string())),
+    }
+}
+
+pub fn entries_from_text(text: &str, format: TextFormat) -> Result<Vec<ManifestEntry>> {
+    let list: EntryList = match format {
+        TextFormat::Json => serde_json::from_str(text).map_err(|e| Error::Decode(e.to_string()))?,
+        TextFormat::Toml => toml::from_str(text).map_err(|e| Error::Decode(e.to_string()))?,
+    };
+    Ok(list.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::FileData;
+    use crate::manifest::ManifestEntry;
+
+    fn entry(path: &str, hash: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            size: Some(hash.len() as u64),
+            last_modified: 1,
+            hash: hash.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_entries_round_trip() {
+        let entries = vec![
+            entry("a.txt", &"a".repeat(64)),
+            entry("sub/c.txt", ""),
+            entry("sub/deeper/b.txt", &"b".repeat(64)),
+            entry("z.txt", &"c".repeat(64)),
+        ];
+        let data = FileData::from_entries("build".to_string(), 1, entries.clone()).unwrap();
+        assert_eq!(data.to_entries(), entries);
+        assert_eq!(data.entries().count(), 4);
+        assert_eq!(data.root.as_ref().unwrap().files()[0].get_path(), format!(".{}a.txt", std::path::MAIN_SEPARATOR));
+
+        assert!(FileData::from_entries(String::new(), 1, vec![entry("../x", "")]).is_err());
+        assert!(FileData::from_entries(String::new(), 1, vec![entry("a", ""), entry("a/b", "")]).is_err());
+        assert!(FileData::from_entries(String::new(), 1, vec![entry("a", ""), entry("a", "")]).is_err());
+        assert!(FileData::from_entries(String::new(), 1, vec![entry("a", "xyz")]).is_err());
+    }
+}
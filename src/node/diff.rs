@@ -1,29 +1,40 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::node::dir::DirectoryNode;
 use crate::node::file::FileNode;
 use crate::node::Node;
 
+#[derive(Clone, Deserialize, Serialize)]
 pub enum FileDiff {
     Add(FileDetail),
     Change(FileDetail),
-    Remove(FileDetail)
+    Remove(FileDetail),
+    Rename { from: FileDetail, to: FileDetail },
 }
 
 impl fmt::Display for FileDiff {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the status letters of `report::DiffStatus`, as in `git diff --name-status`
         match self {
             FileDiff::Add(file) => write!(f, "A: {}", file),
-            FileDiff::Change(file) => write!(f, "C: {}", file),
-            FileDiff::Remove(file) => write!(f, "R: {}", file)
+            FileDiff::Change(file) => write!(f, "M: {}", file),
+            FileDiff::Remove(file) => write!(f, "D: {}", file),
+            FileDiff::Rename { from, to } => write!(f, "R: {} -> {}", from, to),
         }
     }
 }
 
+#[derive(Clone, Deserialize, Serialize)]
 pub struct FileDetail {
     pub path: Arc<str>,
     pub name: String,
-    pub is_file: bool
+    pub is_file: bool,
+    /// Size in bytes of the file as it ends up, when known.
+    pub size: Option<u64>,
 }
 
 impl FileDetail {
@@ -31,26 +42,49 @@ impl FileDetail {
         FileDetail {
             path,
             name,
-            is_file
+            is_file,
+            size: None,
         }
     }
+
+    pub fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
     
     pub fn from(node: &Node) -> Self {
         match node {
             Node::Directory(dir) => FileDetail::new(dir.path.as_ref().unwrap().clone(), dir.name.clone(), false),
-            Node::File(file) => FileDetail::new(file.path.as_ref().unwrap().clone(), file.name.clone(), true)
+            Node::File(file) => FileDetail::from_file(file),
         }
     }
 
     pub fn from_file(file: &FileNode) -> Self {
-        FileDetail::new(file.path.as_ref().unwrap().clone(), file.name.clone(), true)
+        FileDetail::new(file.path.as_ref().unwrap().clone(), file.name.clone(), true).with_size(file.size)
     }
 
     pub fn get_path<T: AsRef<Path>>(&self, base: T) -> PathBuf {
-        if self.is_file {
-            base.as_ref().join(self.path.as_ref()).join(&self.name)
+        base.as_ref().join(self.path.as_ref()).join(&self.name)
+    }
+
+    /// Fails unless [`FileDetail::get_path`] stays inside `base`, which a detail read from a patch
+    /// manifest does not guarantee.
+    pub fn check_path(&self) -> io::Result<()> {
+        let relative = Path::new(self.path.as_ref()).join(&self.name);
+        if relative.components().all(|c| matches!(c, Component::CurDir | Component::Normal(_))) {
+            Ok(())
         } else {
-            base.as_ref().join(self.path.as_ref()).join(&self.name)
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("Path leaves the target folder: {}", self)))
+        }
+    }
+}
+
+impl FileDiff {
+    /// Checks the path of every entry of the diff, see [`FileDetail::check_path`].
+    pub fn check_paths(&self) -> io::Result<()> {
+        match self {
+            FileDiff::Add(detail) | FileDiff::Change(detail) | FileDiff::Remove(detail) => detail.check_path(),
+            FileDiff::Rename { from, to } => from.check_path().and_then(|_| to.check_path()),
         }
     }
 }
@@ -59,4 +93,85 @@ impl fmt::Display for FileDetail {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}{}{}", self.path, std::path::MAIN_SEPARATOR, self.name)
     }
-}
\ No newline at end of file
+}
+
+/// Number of files a list of diffs writes and their total size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl DiffSummary {
+    /// Counts added and changed files; renames and removals copy nothing.
+    pub fn new(diffs: &[FileDiff]) -> Self {
+        let mut summary = DiffSummary::default();
+        for diff in diffs {
+            if let FileDiff::Add(detail) | FileDiff::Change(detail) = diff {
+                if detail.is_file {
+                    summary.files += 1;
+                    summary.bytes += detail.size.unwrap_or(0);
+                }
+            }
+        }
+        summary
+    }
+}
+
+impl fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} files / {:.1} MB", self.files, self.bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Turns each added file whose hash matches a removed file, or a file inside a removed directory,
+/// into a [`FileDiff::Rename`] so the patch moves the file instead of shipping it again.
+pub(crate) fn detect_renames(diffs: Vec<FileDiff>, source: &DirectoryNode, target: &DirectoryNode) -> Vec<FileDiff> {
+    let source_files: BTreeMap<String, &FileNode> = source.files().into_iter().map(|file| (file.get_path(), file)).collect();
+
+    let mut removed: HashMap<[char; 64], VecDeque<&FileNode>> = HashMap::new();
+    for diff in &diffs {
+        if let FileDiff::Remove(detail) = diff {
+            let key = detail.to_string();
+            let files: Vec<&FileNode> = if detail.is_file {
+                source_files.get(&key).into_iter().copied().collect()
+            } else {
+                let prefix = format!("{}{}", key, std::path::MAIN_SEPARATOR);
+                source_files.range(prefix.clone()..).take_while(|(path, _)| path.starts_with(&prefix)).map(|(_, file)| *file).collect()
+            };
+            for file in files.into_iter().filter(|file| file.has_hash()) {
+                removed.entry(file.hash).or_default().push_back(file);
+            }
+        }
+    }
+    if removed.is_empty() {
+        return diffs;
+    }
+
+    let target_files: HashMap<String, &FileNode> = target.files().into_iter().map(|file| (file.get_path(), file)).collect();
+    let mut renamed = HashSet::new();
+    let mut update_list = Vec::with_capacity(diffs.len());
+    for diff in diffs {
+        let from = match &diff {
+            FileDiff::Add(detail) if detail.is_file => target_files.get(&detail.to_string())
+                .filter(|file| file.has_hash())
+                .and_then(|file| removed.get_mut(&file.hash))
+                .and_then(|candidates| candidates.pop_front()),
+            _ => None,
+        };
+        match (from, diff) {
+            (Some(from), FileDiff::Add(to)) => {
+                log::debug!("Detected rename: {} -> {}", from.get_path(), to);
+                renamed.insert(from.get_path());
+                update_list.push(FileDiff::Rename { from: FileDetail::from_file(from), to });
+            }
+            (_, diff) => update_list.push(diff),
+        }
+    }
+
+    update_list.retain(|diff| match diff {
+        FileDiff::Remove(detail) => !(detail.is_file && renamed.contains(&detail.to_string())),
+        _ => true,
+    });
+    update_list
+}
@@ -1,7 +1,8 @@
 use std::cmp::Ordering;
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
-use crate::node::diff::{FileDetail, FileDiff};
+use crate::node::diff::{detect_renames, FileDetail, FileDiff};
+use crate::node::file::FileNode;
 use crate::node::Node;
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -26,14 +27,11 @@ impl DirectoryNode {
     
     pub fn with_capacity(&mut self, capacity: usize) -> &mut Self {
         self.children.reserve(capacity);
-        return self;
+        self
     }
 
     pub fn has_child(&self, child: &Node) -> bool {
-        match self.children.binary_search(child) {
-            Ok(_) => true,
-            Err(_) => false,
-        }
+        self.children.binary_search(child).is_ok()
     }
 
     pub fn remove_child(&mut self, child: &Node) -> Option<Node> {
@@ -56,7 +54,7 @@ impl DirectoryNode {
     pub fn get_path(&self) -> String {
         match self.path {
             None => {
-                format!("{}", self.name)
+                self.name.to_string()
             }
             Some(_) => {
                 format!("{}{}{}", self.path.as_ref().unwrap(), std::path::MAIN_SEPARATOR, self.name)
@@ -69,6 +67,10 @@ impl DirectoryNode {
     }
 
     pub fn get_update_list(&self, other: &Self) -> Vec<FileDiff> {
+        detect_renames(self.collect_update_list(other), self, other)
+    }
+
+    pub(crate) fn collect_update_list(&self, other: &Self) -> Vec<FileDiff> {
         log::debug!("Checking for: {}", self.get_path());
         let path: Arc<str> = Arc::from(self.get_path());
 
@@ -88,8 +90,8 @@ impl DirectoryNode {
                             update_list.push(FileDiff::Add(FileDetail::new(path.clone(), dir.name.clone(), false)));
                             update_list.extend(dir.as_add());
                         },
-                        Node::File(_) => {
-                            update_list.push(FileDiff::Add(FileDetail::new(path.clone(), b.name().clone(), b.is_file())));
+                        Node::File(file) => {
+                            update_list.push(FileDiff::Add(FileDetail::new(path.clone(), file.name.clone(), true).with_size(file.size)));
                         },
                     }
                     j += 1;
@@ -112,10 +114,11 @@ impl DirectoryNode {
             for b in &other.children[j..] {
                 match b {
                     Node::Directory(dir) => {
+                        update_list.push(FileDiff::Add(FileDetail::new(path.clone(), dir.name.clone(), false)));
                         update_list.extend(dir.as_add());
                     }
                     Node::File(file) => {
-                        update_list.push(FileDiff::Add(FileDetail::new(path.clone(), file.name.clone(), true)));
+                        update_list.push(FileDiff::Add(FileDetail::new(path.clone(), file.name.clone(), true).with_size(file.size)));
                     }
                 }
             }
@@ -134,7 +137,7 @@ impl DirectoryNode {
                     update_list.extend(dir.as_add());
                 }
                 Node::File(file) => {
-                    update_list.push(FileDiff::Add(FileDetail::new(path.clone(), file.name.clone(), true)));
+                    update_list.push(FileDiff::Add(FileDetail::new(path.clone(), file.name.clone(), true).with_size(file.size)));
                 }
             }
         }
@@ -154,6 +157,28 @@ impl DirectoryNode {
     pub fn children(&self) -> &Vec<Node> {
         &self.children
     }
+
+    pub fn files(&self) -> Vec<&FileNode> {
+        let mut files = Vec::new();
+        for child in &self.children {
+            match child {
+                Node::File(file) => files.push(file),
+                Node::Directory(dir) => files.extend(dir.files()),
+            }
+        }
+        files
+    }
+
+    pub fn files_mut(&mut self) -> Vec<&mut FileNode> {
+        let mut files = Vec::new();
+        for child in &mut self.children {
+            match child {
+                Node::File(file) => files.push(file),
+                Node::Directory(dir) => files.extend(dir.files_mut()),
+            }
+        }
+        files
+    }
 }
 
 impl PartialEq for DirectoryNode {
@@ -1,6 +1,8 @@
 use std::cmp::Ordering;
 use std::sync::Arc;
+use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::chunk::Chunk;
 use crate::node::diff::{FileDetail, FileDiff};
 
 
@@ -10,8 +12,13 @@ pub struct FileNode {
     pub path: Option<Arc<str>>,
     pub name: String,
     pub last_modified: u64,
+    /// Size in bytes, `None` for manifests written before sizes were recorded.
+    pub size: Option<u64>,
     #[serde(serialize_with = "serialize_hash", deserialize_with = "deserialize_hash")]
     pub hash: [char; 64],
+    /// Content-defined chunks of the file, empty unless the file was chunked.
+    #[serde(default)]
+    pub chunks: Vec<Chunk>,
 }
 
 fn serialize_hash<S>(hash: &[char; 64], serializer: S) -> Result<S::Ok, S::Error>
@@ -27,24 +34,36 @@ fn deserialize_hash<'de, D>(deserializer: D) -> Result<[char; 64], D::Error>
         D: Deserializer<'de>,
 {
     let s: String = Deserialize::deserialize(deserializer)?;
-    let mut chars = s.chars();
-    let mut hash = [' '; 64]; // initialize with spaces
-    for (i, c) in hash.iter_mut().enumerate() {
-        *c = chars.next().unwrap_or(' '); // fill with space if not enough chars
+    let mut hash = [' '; 64]; // a blank hash is a file that was never hashed
+    if s.trim().is_empty() {
+        return Ok(hash);
+    }
+    if !is_valid_hash(&s) {
+        return Err(D::Error::custom(format!("invalid hash {:?}, expected 64 hex digits", s)));
+    }
+    for (c, digit) in hash.iter_mut().zip(s.to_lowercase().chars()) {
+        *c = digit;
     }
     Ok(hash)
 }
 
+/// Whether `hash` is a SHA-256 hash in hex, as every hash of a [`FileNode`] must be.
+pub(crate) fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
 unsafe impl Sync for FileNode {}
 unsafe impl Send for FileNode {}
 
 impl FileNode {
-    pub fn new(path: Arc<str>, name: String, last_modified: u64) -> Self {
+    pub fn new(path: Arc<str>, name: String, last_modified: u64, size: u64) -> Self {
         FileNode {
             path: Some(path),
             name,
             last_modified,
+            size: Some(size),
             hash: [' '; 64],
+            chunks: Vec::new(),
         }
     }
 
@@ -52,6 +71,10 @@ impl FileNode {
         self.hash != [' '; 64]
     }
 
+    pub fn get_hash(&self) -> String {
+        self.hash.iter().collect()
+    }
+
     pub fn get_path(&self) -> String {
         match self.path {
             None => {
@@ -73,6 +96,12 @@ impl FileNode {
     }
 
     pub fn needs_update(&self, other: &Self) -> bool {
+        // a different size settles it without looking at the hashes
+        if let (Some(size), Some(other_size)) = (self.size, other.size) {
+            if size != other_size {
+                return true;
+            }
+        }
         if self.has_hash() && other.has_hash() {
             self.hash != other.hash
         } else {
@@ -86,7 +115,7 @@ impl FileNode {
         }
 
         if self.needs_update(other) {
-            vec![FileDiff::Change(FileDetail::new(self.path.as_ref().unwrap().clone(), self.name.clone(), true))]
+            vec![FileDiff::Change(FileDetail::new(self.path.as_ref().unwrap().clone(), self.name.clone(), true).with_size(other.size))]
         } else {
             Vec::new()
         }
@@ -131,7 +160,21 @@ mod tests {
     #[test]
     fn test_file_node() {
         let path: Arc<str> = Arc::from(String::from("test").as_ref());
-        let file = FileNode::new(path, "test".to_string(), 0);
+        let file = FileNode::new(path, "test".to_string(), 0, 4);
         assert_eq!(file.get_path(), format!("test{}test", path::MAIN_SEPARATOR_STR));
     }
+
+    #[test]
+    fn test_size_mismatch_needs_update() {
+        let path: Arc<str> = Arc::from(".");
+        let mut old = FileNode::new(path.clone(), "test".to_string(), 5, 4);
+        let mut new = FileNode::new(path, "test".to_string(), 5, 4);
+        old.set_hash("a".repeat(64));
+        new.set_hash("a".repeat(64));
+        assert!(!old.needs_update(&new));
+        new.size = Some(8);
+        assert!(old.needs_update(&new));
+        new.size = None;
+        assert!(!old.needs_update(&new));
+    }
 }
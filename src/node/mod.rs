@@ -8,6 +8,7 @@ pub mod diff;
 pub mod dir;
 pub mod file;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub enum Node {
     File(FileNode),
@@ -40,16 +41,13 @@ impl Node {
     pub fn get_update_list(&self, other: &Self) -> Vec<FileDiff> {
         match (self, other) {
             (Node::File(a), Node::File(b)) => a.get_update_list(b),
-            (Node::Directory(a), Node::Directory(b)) => a.get_update_list(b),
+            (Node::Directory(a), Node::Directory(b)) => a.collect_update_list(b),
             _ => panic!("Cannot compare file and directory"),
         }
     }
 
     pub fn is_file(&self) -> bool {
-        match self {
-            Node::File(_) => true,
-            _ => false,
-        }
+        matches!(self, Node::File(_))
     }
 
     pub fn restore_path(&mut self, path: Option<Arc<str>>) {
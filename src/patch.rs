@@ -0,0 +1,113 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::node::diff::{FileDetail, FileDiff};
+use crate::transaction::Transaction;
+
+/// Copies every added or changed entry of `diffs` from `path` into the patch folder `output`.
+pub fn generate_patch<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, diffs: &[FileDiff]) -> io::Result<()> {
+    let path = path.as_ref();
+    let output = output.as_ref();
+    for diff in diffs {
+        match diff {
+            FileDiff::Change(detail) | FileDiff::Add(detail) => {
+                log::info!("Adding file: {}", detail);
+                log::debug!("Copying from {} to {}", detail.get_path(path).display(), detail.get_path(output).display());
+                if detail.is_file {
+                    let target_path = detail.get_path(output);
+                    if let Some(parent) = target_path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(detail.get_path(path), target_path)?;
+                } else {
+                    fs::create_dir_all(detail.get_path(output))?;
+                }
+            },
+            FileDiff::Remove(_) | FileDiff::Rename { .. } => {},
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ApplyOptions {
+    /// Hardlinks files with identical content to each other instead of writing separate copies.
+    pub hardlink: bool,
+}
+
+/// Applies `diffs` to the installed tree at `install_dir`, reading new content from `patch_dir`.
+///
+/// The patch is applied as a [`Transaction`]: if any step fails the installed tree is left as it was.
+pub fn apply_patch<P: AsRef<Path>, Q: AsRef<Path>>(patch_dir: P, install_dir: Q, diffs: &[FileDiff]) -> io::Result<()> {
+    let patch_dir = patch_dir.as_ref();
+    apply_diffs(install_dir.as_ref(), diffs, &BTreeMap::new(), &ApplyOptions::default(), |detail, _, staged| {
+        log::debug!("Copying from {} to {}", detail.get_path(patch_dir).display(), staged.display());
+        fs::copy(detail.get_path(patch_dir), staged).map(|_| ())
+    })
+}
+
+/// Applies `diffs` in a [`Transaction`], calling `write` with the installed path and the staging
+/// path to materialize each added or changed file.
+///
+/// Files listed in `duplicates` are not passed to `write` but copied, or hardlinked, from the
+/// file they duplicate, which must come earlier in `diffs`.
+///
+/// Every path is checked before anything is written, so diffs read from a patch cannot reach
+/// outside `install_dir`.
+///
+/// Renamed files are moved aside first, so they can come from a removed directory, then removals
+/// are applied so an entry can change from a file to a directory (or back) within a single patch.
+pub(crate) fn apply_diffs<F>(install_dir: &Path, diffs: &[FileDiff], duplicates: &BTreeMap<String, String>, options: &ApplyOptions, mut write: F) -> io::Result<()>
+    where
+        F: FnMut(&FileDetail, &Path, &Path) -> io::Result<()>,
+{
+    for diff in diffs {
+        diff.check_paths()?;
+    }
+    let mut transaction = Transaction::begin(install_dir)?;
+    let mut moved = Vec::new();
+    for diff in diffs {
+        if let FileDiff::Rename { from, to } = diff {
+            moved.push((to.get_path(install_dir), transaction.stash(from.get_path(install_dir))));
+        }
+    }
+    for diff in diffs {
+        if let FileDiff::Remove(detail) = diff {
+            transaction.remove(detail.get_path(install_dir));
+        }
+    }
+    for (target, staged) in moved {
+        transaction.write(target, staged);
+    }
+
+    let mut staged_files = HashMap::new();
+
+    for diff in diffs {
+        match diff {
+            FileDiff::Add(detail) | FileDiff::Change(detail) => {
+                let target = detail.get_path(install_dir);
+                if detail.is_file {
+                    log::debug!("Staging file: {}", detail);
+                    let key = detail.to_string();
+                    let staged = transaction.stage_path();
+                    let result = match duplicates.get(&key).and_then(|original| staged_files.get(original)) {
+                        Some(original) if options.hardlink => fs::hard_link(original, &staged),
+                        Some(original) => fs::copy(original, &staged).map(|_| ()),
+                        None => write(detail, &target, &staged),
+                    };
+                    if let Err(e) = result {
+                        transaction.abort()?;
+                        return Err(e);
+                    }
+                    staged_files.insert(key, staged.clone());
+                    transaction.write(target, staged);
+                } else {
+                    transaction.create_dir(target);
+                }
+            },
+            FileDiff::Remove(_) | FileDiff::Rename { .. } => {},
+        }
+    }
+    transaction.commit()
+}
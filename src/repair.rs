@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use crate::bundle::PatchBundle;
+use crate::data::FileData;
+use crate::hash::{calculate_hash, copy_with_hash};
+use crate::node::diff::{FileDetail, FileDiff};
+use crate::patch::{apply_diffs, ApplyOptions};
+use crate::verify::{verify, VerifyReport};
+
+/// Where [`repair`] takes the original content of broken files from.
+pub enum RepairSource<'a> {
+    /// A full copy of the release, such as the folder it was scanned from.
+    Directory(&'a Path),
+    /// A patch bundle; only files stored whole in its payload can be restored.
+    Bundle(&'a PatchBundle),
+}
+
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub repaired: Vec<String>,
+    /// Broken files the source had no content for.
+    pub unavailable: Vec<String>,
+}
+
+/// Verifies `install_dir` against `data` and restores every missing or corrupt file from `source`.
+///
+/// Extra files are left in place.
+#[cfg(feature = "async")]
+pub async fn repair<P: AsRef<Path>>(install_dir: P, source: RepairSource<'_>, data: &FileData) -> io::Result<RepairReport> {
+    let report = verify(install_dir.as_ref(), data).await?;
+    restore(install_dir.as_ref(), source, data, &report)
+}
+
+/// Verifies `install_dir` against `data` and restores every missing or corrupt file from `source`.
+///
+/// Extra files are left in place.
+#[cfg(not(feature = "async"))]
+pub fn repair<P: AsRef<Path>>(install_dir: P, source: RepairSource<'_>, data: &FileData) -> io::Result<RepairReport> {
+    let report = verify(install_dir.as_ref(), data)?;
+    restore(install_dir.as_ref(), source, data, &report)
+}
+
+fn restore(install_dir: &Path, source: RepairSource<'_>, data: &FileData, report: &VerifyReport) -> io::Result<RepairReport> {
+    let files: BTreeMap<String, _> = match data.root.as_ref() {
+        Some(root) => root.files().into_iter().map(|file| (file.get_path(), file)).collect(),
+        None => BTreeMap::new(),
+    };
+
+    let mut result = RepairReport::default();
+    let mut diffs = Vec::new();
+    for key in report.missing.iter().chain(report.corrupt.iter()) {
+        let detail = FileDetail::from_file(files[key]);
+        let available = match &source {
+            RepairSource::Directory(dir) => detail.get_path(dir).is_file(),
+            RepairSource::Bundle(bundle) => {
+                let stored = bundle.manifest.duplicates.get(key).unwrap_or(key);
+                bundle.payload.contains_key(stored) && !bundle.manifest.deltas.contains_key(stored)
+            }
+        };
+        if available {
+            log::info!("Repairing: {}", key);
+            result.repaired.push(key.clone());
+            diffs.push(FileDiff::Change(detail));
+        } else {
+            log::warn!("No content to repair: {}", key);
+            result.unavailable.push(key.clone());
+        }
+    }
+
+    if diffs.is_empty() {
+        return Ok(result);
+    }
+    apply_diffs(install_dir, &diffs, &BTreeMap::new(), &ApplyOptions::default(), |detail, _, staged| {
+        let key = detail.to_string();
+        let hash = match &source {
+            RepairSource::Directory(dir) => copy_with_hash(&mut File::open(detail.get_path(dir))?, &mut File::create(staged)?)?,
+            RepairSource::Bundle(bundle) => {
+                let content = &bundle.payload[bundle.manifest.duplicates.get(&key).unwrap_or(&key)];
+                fs::write(staged, content)?;
+                calculate_hash(content)
+            }
+        };
+        if hash != files[&key].get_hash() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Repair source does not match the file data: {}", key)));
+        }
+        Ok(())
+    })?;
+    Ok(result)
+}
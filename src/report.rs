@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use serde::{Deserialize, Serialize};
+use crate::data::FileData;
+use crate::node::diff::{FileDetail, FileDiff};
+use crate::node::file::FileNode;
+
+/// Machine-readable layouts for a list of [`DiffRecord`]s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// A single JSON array.
+    Json,
+    /// One JSON object per line.
+    JsonLines,
+    /// A header line, then one line per record.
+    Csv,
+    /// `git diff --name-status` lines: the status letter, then the path, or both paths of a rename,
+    /// separated by tabs.
+    NameStatus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DiffStatus {
+    #[serde(rename = "A")]
+    Added,
+    #[serde(rename = "M")]
+    Modified,
+    #[serde(rename = "D")]
+    Deleted,
+    #[serde(rename = "R")]
+    Renamed,
+}
+
+impl DiffStatus {
+    pub fn letter(&self) -> char {
+        match self {
+            DiffStatus::Added => 'A',
+            DiffStatus::Modified => 'M',
+            DiffStatus::Deleted => 'D',
+            DiffStatus::Renamed => 'R',
+        }
+    }
+}
+
+/// One [`FileDiff`] with the size and hash of the file on each side.
+///
+/// Paths are relative to the release root with `/` separators, as in a
+/// [`ManifestEntry`](crate::manifest::ManifestEntry), and end with `/` for directories. Sizes and
+/// hashes are only set for files on the side they exist on.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DiffRecord {
+    pub status: DiffStatus,
+    pub path: String,
+    /// Path before a rename.
+    pub old_path: Option<String>,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+}
+
+impl DiffRecord {
+    /// Describes each of `diffs`, computed from `source` to `target`.
+    pub fn collect(diffs: &[FileDiff], source: &FileData, target: &FileData) -> Vec<DiffRecord> {
+        let source_files = files_by_path(source);
+        let target_files = files_by_path(target);
+        let old = |detail: &FileDetail| source_files.get(&detail.to_string()).copied();
+        let new = |detail: &FileDetail| target_files.get(&detail.to_string()).copied();
+
+        diffs.iter().map(|diff| {
+            let (status, detail, from, old_file, new_file) = match diff {
+                FileDiff::Add(detail) => (DiffStatus::Added, detail, None, None, new(detail)),
+                FileDiff::Change(detail) => (DiffStatus::Modified, detail, None, old(detail), new(detail)),
+                FileDiff::Remove(detail) => (DiffStatus::Deleted, detail, None, old(detail), None),
+                FileDiff::Rename { from, to } => (DiffStatus::Renamed, to, Some(from), old(from), new(to)),
+            };
+            DiffRecord {
+                status,
+                path: relative_path(detail),
+                old_path: from.map(relative_path),
+                old_size: old_file.and_then(|file| file.size),
+                new_size: new_file.and_then(|file| file.size),
+                old_hash: old_file.filter(|file| file.has_hash()).map(FileNode::get_hash),
+                new_hash: new_file.filter(|file| file.has_hash()).map(FileNode::get_hash),
+            }
+        }).collect()
+    }
+}
+
+fn files_by_path(data: &FileData) -> HashMap<String, &FileNode> {
+    data.root.iter().flat_map(|root| root.files()).map(|file| (file.get_path(), file)).collect()
+}
+
+fn relative_path(detail: &FileDetail) -> String {
+    let path = detail.to_string();
+    let path = path.strip_prefix('.').unwrap_or(&path).trim_start_matches(std::path::MAIN_SEPARATOR);
+    let path = path.replace(std::path::MAIN_SEPARATOR, "/");
+    if detail.is_file { path } else { format!("{}/", path) }
+}
+
+/// Writes `records` to `out` in `format`.
+pub fn write_diffs<W: Write>(records: &[DiffRecord], format: DiffFormat, mut out: W) -> io::Result<()> {
+    match format {
+        DiffFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, records)?;
+            writeln!(out)?;
+        }
+        DiffFormat::JsonLines => {
+            for record in records {
+                serde_json::to_writer(&mut out, record)?;
+                writeln!(out)?;
+            }
+        }
+        DiffFormat::Csv => {
+            writeln!(out, "status,path,old_path,old_size,new_size,old_hash,new_hash")?;
+            for record in records {
+                let fields = [
+                    record.status.letter().to_string(),
+                    record.path.clone(),
+                    record.old_path.clone().unwrap_or_default(),
+                    record.old_size.map(|size| size.to_string()).unwrap_or_default(),
+                    record.new_size.map(|size| size.to_string()).unwrap_or_default(),
+                    record.old_hash.clone().unwrap_or_default(),
+                    record.new_hash.clone().unwrap_or_default(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+        DiffFormat::NameStatus => {
+            for record in records {
+                match &record.old_path {
+                    Some(old_path) => writeln!(out, "{}\t{}\t{}", record.status.letter(), old_path, record.path)?,
+                    None => writeln!(out, "{}\t{}", record.status.letter(), record.path)?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Quotes a field holding a comma, a quote or a line break, doubling its quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::{write_diffs, DiffFormat, DiffRecord, DiffStatus};
+
+    #[test]
+    fn test_write_formats() {
+        let records = vec![
+            DiffRecord {
+                status: DiffStatus::Modified,
+                path: "a,b.txt".to_string(),
+                old_path: None,
+                old_size: Some(1),
+                new_size: Some(2),
+                old_hash: Some("1".repeat(64)),
+                new_hash: Some("2".repeat(64)),
+            },
+            DiffRecord {
+                status: DiffStatus::Renamed,
+                path: "sub/new.txt".to_string(),
+                old_path: Some("old.txt".to_string()),
+                old_size: Some(3),
+                new_size: Some(3),
+                old_hash: None,
+                new_hash: None,
+            },
+        ];
+        let write = |format| {
+            let mut out = Vec::new();
+            write_diffs(&records, format, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(write(DiffFormat::NameStatus), "M\ta,b.txt\nR\told.txt\tsub/new.txt\n");
+        let csv = write(DiffFormat::Csv);
+        assert_eq!(csv.lines().nth(1).unwrap(), format!("M,\"a,b.txt\",,1,2,{},{}", "1".repeat(64), "2".repeat(64)));
+        assert_eq!(csv.lines().nth(2).unwrap(), "R,sub/new.txt,old.txt,3,3,,");
+
+        let lines: Vec<DiffRecord> = write(DiffFormat::JsonLines).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines, records);
+        let json: Vec<DiffRecord> = serde_json::from_str(&write(DiffFormat::Json)).unwrap();
+        assert_eq!(json, records);
+        assert!(write(DiffFormat::JsonLines).starts_with("{\"status\":\"M\","));
+    }
+}
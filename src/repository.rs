@@ -0,0 +1,242 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use crate::bundle::PatchBundle;
+use crate::data::{updater_version, FileData};
+use crate::store::ObjectStore;
+
+const PATCHES_DIR: &str = "patches";
+const PATCH_EXTENSION: &str = ".bin.gz";
+const CHANNELS_FILE: &str = "channels.bin.gz";
+
+/// A history of releases with increasing versions, kept in an [`ObjectStore`].
+///
+/// Adding a release stores the incremental patch from the previous version, and direct patches
+/// are built and kept as clients update, so an update can use whichever is smaller.
+///
+/// Named channels, such as stable or beta, each point at one of the versions so clients can follow
+/// a track rather than the latest version.
+pub struct Repository {
+    root: PathBuf,
+    store: ObjectStore,
+}
+
+impl Repository {
+    pub fn open<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let store = ObjectStore::open(&root)?;
+        fs::create_dir_all(root.join(PATCHES_DIR))?;
+        Ok(Repository { root, store })
+    }
+
+    pub fn store(&self) -> &ObjectStore {
+        &self.store
+    }
+
+    /// Lists the stored versions, oldest first.
+    pub fn versions(&self) -> io::Result<Vec<u64>> {
+        let mut versions: Vec<u64> = self.store.manifests()?.iter().filter_map(|name| name.parse().ok()).collect();
+        versions.sort_unstable();
+        Ok(versions)
+    }
+
+    pub fn latest(&self) -> io::Result<Option<u64>> {
+        Ok(self.versions()?.last().copied())
+    }
+
+    pub fn manifest(&self, version: u64) -> io::Result<FileData> {
+        self.store.load_manifest(&version.to_string())
+    }
+
+    /// Stores `data`, scanned from `path`, as the next version and writes the incremental patch
+    /// from the previous one. Returns the new version.
+    pub fn add_release<P: AsRef<Path>>(&self, path: P, mut data: FileData) -> io::Result<u64> {
+        let previous = self.latest()?;
+        let version = previous.map_or(1, |v| v + 1);
+        data.version = version;
+        self.store.add_release(&version.to_string(), path, &data)?;
+        if let Some(previous) = previous {
+            self.write_patch(previous, version)?;
+        }
+        Ok(version)
+    }
+
+    /// Builds and stores the direct patch from version `from` to version `to`, unless it is stored
+    /// already. Returns its path.
+    pub fn build_direct_patch(&self, from: u64, to: u64) -> io::Result<PathBuf> {
+        let path = self.patch_path(from, to);
+        if !path.is_file() {
+            self.write_patch(from, to)?;
+        }
+        Ok(path)
+    }
+
+    /// Returns the patch files to apply, in order, to upgrade version `from` to version `to`.
+    ///
+    /// Only reads the repository: the chain of stored patches with the smallest total size is
+    /// picked, so the direct patch is only considered once [`Repository::build_direct_patch`]
+    /// stored it, as updating does.
+    pub fn update_path(&self, from: u64, to: u64) -> io::Result<Vec<PathBuf>> {
+        if from == to {
+            return Ok(Vec::new());
+        }
+
+        let mut edges: BTreeMap<u64, Vec<(u64, u64)>> = BTreeMap::new();
+        for (start, end) in self.patches()? {
+            let size = fs::metadata(self.patch_path(start, end))?.len();
+            edges.entry(start).or_default().push((end, size));
+        }
+
+        // dijkstra over the stored patches, weighted by file size
+        let mut best: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+        let mut queue = BinaryHeap::from([Reverse((0, from))]);
+        while let Some(Reverse((size, version))) = queue.pop() {
+            if version == to {
+                break;
+            }
+            if best.get(&version).is_some_and(|&(known, _)| known < size) {
+                continue;
+            }
+            for &(next, patch_size) in edges.get(&version).into_iter().flatten() {
+                let total = size + patch_size;
+                if next != from && best.get(&next).is_none_or(|&(known, _)| total < known) {
+                    best.insert(next, (total, version));
+                    queue.push(Reverse((total, next)));
+                }
+            }
+        }
+
+        let mut chain = Vec::new();
+        let mut version = to;
+        while version != from {
+            let &(_, previous) = best.get(&version)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No patches lead from version {} to {}", from, to)))?;
+            chain.push(self.patch_path(previous, version));
+            version = previous;
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Upgrades `install_dir` from version `from` to the latest version. Returns the version installed.
+    ///
+    /// Each patch of the [`Repository::update_path`] chain is applied as its own transaction, so a
+    /// failure leaves `install_dir` at the last version reached, which the error names.
+    pub fn update<P: AsRef<Path>>(&self, install_dir: P, from: u64) -> io::Result<u64> {
+        let latest = self.latest()?.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Repository has no release"))?;
+        self.update_to(install_dir, from, latest)?;
+        Ok(latest)
+    }
+
+    /// Moves `install_dir` from version `from` to the version `channel` points at. Returns that version.
+    ///
+    /// A failure leaves `install_dir` at the last version reached, as with [`Repository::update`].
+    pub fn update_channel<P: AsRef<Path>>(&self, install_dir: P, from: u64, channel: &str) -> io::Result<u64> {
+        let version = self.channel(channel)?.ok_or_else(|| no_channel(channel))?;
+        self.update_to(install_dir, from, version)?;
+        Ok(version)
+    }
+
+    fn update_to<P: AsRef<Path>>(&self, install_dir: P, from: u64, to: u64) -> io::Result<()> {
+        let metadata = self.manifest(to)?.metadata;
+        if !metadata.supports_updater(&updater_version()) {
+            let min = metadata.min_updater_version.unwrap();
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("Version {} needs updater {} or newer", to, min)));
+        }
+        if from != to {
+            self.build_direct_patch(from, to)?;
+        }
+        let mut reached = from;
+        for patch in self.update_path(from, to)? {
+            log::info!("Applying {}", patch.display());
+            let result = PatchBundle::load(patch).and_then(|bundle| {
+                bundle.apply(install_dir.as_ref())?;
+                Ok(bundle.manifest.to_version)
+            });
+            reached = result.map_err(|e| io::Error::new(e.kind(), format!("Update stopped at version {}: {}", reached, e)))?;
+        }
+        Ok(())
+    }
+
+    /// Returns every channel with the version it points at.
+    pub fn channels(&self) -> io::Result<BTreeMap<String, u64>> {
+        let path = self.root.join(CHANNELS_FILE);
+        if !path.is_file() {
+            return Ok(BTreeMap::new());
+        }
+        let mut bytes = Vec::new();
+        GzDecoder::new(File::open(path)?).read_to_end(&mut bytes)?;
+        bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn channel(&self, name: &str) -> io::Result<Option<u64>> {
+        Ok(self.channels()?.get(name).copied())
+    }
+
+    /// Points `channel` at `version`, creating the channel if needed.
+    pub fn set_channel(&self, channel: &str, version: u64) -> io::Result<()> {
+        if channel.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Channel name is empty"));
+        }
+        if !self.versions()?.contains(&version) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No version {}", version)));
+        }
+        let mut channels = self.channels()?;
+        channels.insert(channel.to_string(), version);
+        self.save_channels(&channels)
+    }
+
+    /// Points channel `to` at the version channel `from` points at. Returns that version.
+    pub fn promote(&self, from: &str, to: &str) -> io::Result<u64> {
+        let version = self.channel(from)?.ok_or_else(|| no_channel(from))?;
+        log::info!("Promoting version {} from {} to {}", version, from, to);
+        self.set_channel(to, version)?;
+        Ok(version)
+    }
+
+    fn save_channels(&self, channels: &BTreeMap<String, u64>) -> io::Result<()> {
+        let encoded = bincode::serialize(channels).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // write then rename, so clients never read a half written file
+        let temp = self.root.join(format!("{}.tmp", CHANNELS_FILE));
+        let mut encoder = GzEncoder::new(File::create(&temp)?, Compression::default());
+        encoder.write_all(&encoded)?;
+        encoder.finish()?;
+        fs::rename(temp, self.root.join(CHANNELS_FILE))
+    }
+
+    /// Lists the stored patches as (from, to) version pairs.
+    pub fn patches(&self) -> io::Result<Vec<(u64, u64)>> {
+        let mut patches = Vec::new();
+        for entry in fs::read_dir(self.root.join(PATCHES_DIR))? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            let versions = name.strip_suffix(PATCH_EXTENSION)
+                .and_then(|name| name.split_once('-'))
+                .and_then(|(from, to)| Some((from.parse().ok()?, to.parse().ok()?)));
+            if let Some(versions) = versions {
+                patches.push(versions);
+            }
+        }
+        patches.sort_unstable();
+        Ok(patches)
+    }
+
+    fn write_patch(&self, from: u64, to: u64) -> io::Result<()> {
+        log::info!("Building patch {} -> {}", from, to);
+        self.store.build_patch(&from.to_string(), &to.to_string())?.save(self.patch_path(from, to))
+    }
+
+    fn patch_path(&self, from: u64, to: u64) -> PathBuf {
+        self.root.join(PATCHES_DIR).join(format!("{}-{}{}", from, to, PATCH_EXTENSION))
+    }
+}
+
+fn no_channel(channel: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("No channel named {}", channel))
+}
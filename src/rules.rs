@@ -0,0 +1,274 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// File at the root of a scanned tree listing patterns to leave out of the release.
+pub const IGNORE_FILE: &str = ".releaseignore";
+
+/// Ordered include and exclude patterns deciding which paths a scan keeps.
+///
+/// Patterns have gitignore semantics and are matched against paths relative to the scan root,
+/// with `/` separators. A pattern with a `/` at the start or in the middle is anchored to the root,
+/// otherwise it matches at any depth. `*` and `?` never match `/`, `**` matches any number of
+/// directories and a trailing `/` restricts the pattern to directories.
+///
+/// The last pattern matching a path decides it; a path no pattern matches follows its directory.
+/// Nothing inside an excluded directory is scanned, and everything inside an included directory is
+/// kept unless a later pattern excludes it. Once a set has an include pattern, the root is no
+/// longer kept by default, so only included paths are scanned.
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    include_only: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    segments: Vec<String>,
+    include: bool,
+    dir_only: bool,
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        RuleSet::default()
+    }
+
+    /// A rule set excluding each of `patterns`, as lines of a gitignore file.
+    pub fn from_patterns<I: IntoIterator<Item = S>, S: AsRef<str>>(patterns: I) -> Self {
+        let mut rules = RuleSet::new();
+        for pattern in patterns {
+            rules.exclude(pattern.as_ref());
+        }
+        rules
+    }
+
+    /// Reads the [`IGNORE_FILE`] of `root`, if there is one.
+    pub fn load<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let path = root.as_ref().join(IGNORE_FILE);
+        if !path.is_file() {
+            return Ok(RuleSet::new());
+        }
+        Ok(RuleSet::from_patterns(fs::read_to_string(path)?.lines()))
+    }
+
+    /// Reads a rules file: one pattern per line, `+` before the patterns to include and `-` or
+    /// nothing before the patterns to exclude. Blank lines and `#` comments are skipped.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut rules = RuleSet::new();
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim_start();
+            match line.strip_prefix('+') {
+                Some(pattern) => rules.include(pattern.trim_start()),
+                None => rules.exclude(line.strip_prefix('-').map_or(line, str::trim_start)),
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Adds an exclude pattern, written as a line of a gitignore file, so a leading `!` re-includes
+    /// what an earlier pattern excluded.
+    pub fn exclude(&mut self, line: &str) {
+        self.push(line, false);
+    }
+
+    /// Adds an include pattern. A leading `!` turns it into an exclude pattern.
+    pub fn include(&mut self, line: &str) {
+        if self.push(line, true) {
+            self.include_only = true;
+        }
+    }
+
+    /// Adds the patterns of `other` after the ones of this set.
+    pub fn extend(&mut self, other: RuleSet) {
+        self.rules.extend(other.rules);
+        self.include_only |= other.include_only;
+    }
+
+    /// Whether the scan root itself is kept, which is where paths no pattern matches start from.
+    pub fn includes_root(&self) -> bool {
+        !self.include_only
+    }
+
+    /// The decision of the last pattern matching `path`, relative to the scan root with `/`
+    /// separators: `Some(true)` to include it, `Some(false)` to exclude it.
+    pub fn matches(&self, path: &str, is_dir: bool) -> Option<bool> {
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        self.rules.iter().rev()
+            .find(|rule| (is_dir || !rule.dir_only) && match_segments(&rule.segments, &parts))
+            .map(|rule| rule.include)
+    }
+
+    /// Parses a gitignore line into a rule. Returns whether it added one that includes.
+    fn push(&mut self, line: &str, include: bool) -> bool {
+        let mut pattern = line.trim_end_matches(['\r', '\n']);
+        if !pattern.ends_with("\\ ") {
+            pattern = pattern.trim_end();
+        }
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return false;
+        }
+
+        let negated = pattern.starts_with('!');
+        // a leading `!` negates, a leading `\` escapes a literal `!` or `#`
+        if negated || pattern.starts_with("\\!") || pattern.starts_with("\\#") {
+            pattern = &pattern[1..];
+        }
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        // `./` anchors to the root like a leading `/`, as the old prefix list meant it
+        let (pattern, root_relative) = match pattern.strip_prefix("./") {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        if pattern.is_empty() {
+            return false;
+        }
+
+        let anchored = root_relative || pattern.contains('/');
+        let mut segments: Vec<String> = pattern.trim_start_matches('/').split('/').map(str::to_string).collect();
+        if !anchored {
+            segments.insert(0, "**".to_string());
+        }
+        let include = include != negated;
+        self.rules.push(Rule { segments, include, dir_only });
+        include
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, [])) if first == "**" => !path.is_empty(),
+        Some((first, rest)) if first == "**" => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path)) => match_glob(first.as_bytes(), name.as_bytes()) && match_segments(rest, path),
+            None => false,
+        },
+    }
+}
+
+/// Matches one path segment against `*`, `?`, `[...]` and `\` escapes.
+fn match_glob(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // where to resume after the last `*`: pattern index past it and the name index it is at
+    let mut star = None;
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => match_class(&pattern[p..], name[n]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == name[n]).then_some(2),
+            Some(&c) => (c == name[n]).then_some(1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(step), _) => {
+                p += step;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                p = star_p;
+                n = star_n + 1;
+                star = Some((star_p, star_n + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Matches a `[...]` class at the start of `pattern`, returning its length on a match.
+fn match_class(pattern: &[u8], c: u8) -> Option<usize> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(i)?;
+        if start == b']' && !first {
+            break;
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&end| end != b']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+    (matched != negated).then_some(i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rules::RuleSet;
+
+    #[test]
+    fn test_gitignore_patterns() {
+        let rules = RuleSet::from_patterns([
+            "# comment",
+            "*.pdb",
+            "**/*.log",
+            "!keep.log",
+            "/build/",
+            "docs/*.md",
+            "cache/",
+            "data/**",
+            "file[0-9].txt",
+            "./target",
+        ]);
+        assert_eq!(rules.matches("a.pdb", false), Some(false));
+        assert_eq!(rules.matches("sub/deep/a.pdb", false), Some(false));
+        assert_eq!(rules.matches("sub/x.log", false), Some(false));
+        assert_eq!(rules.matches("sub/keep.log", false), Some(true));
+        assert_eq!(rules.matches("build", true), Some(false));
+        assert_eq!(rules.matches("build", false), None);
+        assert_eq!(rules.matches("sub/build", true), None);
+        assert_eq!(rules.matches("docs/readme.md", false), Some(false));
+        assert_eq!(rules.matches("docs/sub/readme.md", false), None);
+        assert_eq!(rules.matches("other/docs/readme.md", false), None);
+        assert_eq!(rules.matches("sub/cache", true), Some(false));
+        assert_eq!(rules.matches("data", true), None);
+        assert_eq!(rules.matches("data/a/b", false), Some(false));
+        assert_eq!(rules.matches("file3.txt", false), Some(false));
+        assert_eq!(rules.matches("fileA.txt", false), None);
+        assert_eq!(rules.matches("main.rs", false), None);
+        assert_eq!(rules.matches("target", true), Some(false));
+        assert_eq!(rules.matches("sub/target", true), None);
+    }
+
+    #[test]
+    fn test_include_rules() {
+        let mut rules = RuleSet::new();
+        rules.include("bin/");
+        rules.include("data/**/*.pak");
+        rules.include("config/*.toml");
+        rules.exclude("*.pdb");
+        assert!(!rules.includes_root());
+        assert_eq!(rules.matches("bin", true), Some(true));
+        assert_eq!(rules.matches("bin/sub/lib.dll", false), None);
+        assert_eq!(rules.matches("bin/app.pdb", false), Some(false));
+        assert_eq!(rules.matches("data/maps/a.pak", false), Some(true));
+        assert_eq!(rules.matches("data/maps/a.txt", false), None);
+        assert_eq!(rules.matches("config/app.toml", false), Some(true));
+        assert_eq!(rules.matches("config/sub/app.toml", false), None);
+        assert_eq!(rules.matches("data", true), None);
+
+        // a later exclude overrides an earlier include, and the other way round
+        rules.exclude("bin/tools/");
+        rules.include("bin/tools/keep.exe");
+        assert_eq!(rules.matches("bin/tools", true), Some(false));
+        assert_eq!(rules.matches("bin/tools/keep.exe", false), Some(true));
+        rules.include("bin/tools/");
+        assert_eq!(rules.matches("bin/tools", true), Some(true));
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use crate::data::FileData;
+use crate::delta::{block_size_for, Signature};
+use crate::fs::generate_file_data_from_path;
+use crate::node::diff::FileDetail;
+
+/// What a client actually has installed: the scanned file data plus the block [`Signature`] of
+/// every file, so a patch can be built against it without knowing which release it came from.
+#[derive(Deserialize, Serialize)]
+pub struct InstallSignature {
+    pub data: FileData,
+    pub signatures: BTreeMap<String, Signature>,
+}
+
+impl InstallSignature {
+    #[cfg(feature = "async")]
+    pub async fn scan<P: AsRef<Path>>(install_dir: P, ignore: &[String]) -> io::Result<Self> {
+        let data = generate_file_data_from_path(install_dir.as_ref(), ignore).await?;
+        Self::from_data(install_dir.as_ref(), data)
+    }
+
+    #[cfg(not(feature = "async"))]
+    pub fn scan<P: AsRef<Path>>(install_dir: P, ignore: &[String]) -> io::Result<Self> {
+        let data = generate_file_data_from_path(install_dir.as_ref(), ignore)?;
+        Self::from_data(install_dir.as_ref(), data)
+    }
+
+    fn from_data(install_dir: &Path, data: FileData) -> io::Result<Self> {
+        let mut signatures = BTreeMap::new();
+        if let Some(root) = data.root.as_ref() {
+            for file in root.files() {
+                let content = fs::read(FileDetail::from_file(file).get_path(install_dir))?;
+                signatures.insert(file.get_path(), Signature::new(&content, block_size_for(content.len())));
+            }
+        }
+        Ok(InstallSignature { data, signatures })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut decoder = GzDecoder::new(file);
+        let mut bytes = Vec::new();
+        decoder.read_to_end(&mut bytes)?;
+        let mut signature: InstallSignature = bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(root) = signature.data.root.as_mut() {
+            root.restore_path(None);
+        }
+        Ok(signature)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let encoded = bincode::serialize(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let file = File::create(path)?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(&encoded)?;
+        encoder.finish()?;
+        Ok(())
+    }
+}
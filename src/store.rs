@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::bundle::{ContentSource, PatchBundle};
+use crate::chunk::{Chunk, Chunker};
+use crate::data::FileData;
+use crate::error::Error;
+use crate::hash::copy_with_hash;
+use crate::node::diff::FileDetail;
+use crate::node::dir::DirectoryNode;
+use crate::node::file::{is_valid_hash, FileNode};
+use crate::patch::{apply_diffs, ApplyOptions};
+
+const OBJECTS_DIR: &str = "objects";
+const MANIFESTS_DIR: &str = "manifests";
+const MANIFEST_EXTENSION: &str = ".bin.gz";
+
+/// A content-addressed store of release files.
+///
+/// Every file is stored once as a blob named after its SHA-256 hash, and each release is a named
+/// [`FileData`] referencing those blobs, so storing a release only adds the files that are new.
+/// Chunked files are stored as one blob per chunk instead, so only their new chunks are added.
+pub struct ObjectStore {
+    root: PathBuf,
+}
+
+impl ObjectStore {
+    pub fn open<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(root.join(OBJECTS_DIR))?;
+        fs::create_dir_all(root.join(MANIFESTS_DIR))?;
+        Ok(ObjectStore { root })
+    }
+
+    /// Where the blob hashing to `hash` is stored. Hashes come from manifests, so anything but a
+    /// SHA-256 hash is rejected rather than joined onto the store root.
+    pub fn object_path(&self, hash: &str) -> io::Result<PathBuf> {
+        if !is_valid_hash(hash) {
+            return Err(Error::InvalidHash(hash.to_string()).into());
+        }
+        let (prefix, rest) = hash.split_at(2);
+        Ok(self.root.join(OBJECTS_DIR).join(prefix).join(rest))
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.object_path(hash).is_ok_and(|path| path.is_file())
+    }
+
+    pub fn read(&self, hash: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.object_path(hash)?)
+    }
+
+    /// Copies a file into the store and returns its hash.
+    pub fn add_file<P: AsRef<Path>>(&self, path: P) -> io::Result<String> {
+        let temp = self.temp_path();
+        let hash = match copy_with_hash(&mut File::open(path)?, &mut File::create(&temp)?) {
+            Ok(hash) => hash,
+            Err(e) => {
+                fs::remove_file(&temp)?;
+                return Err(e.into());
+            }
+        };
+        self.insert(temp, &hash)?;
+        Ok(hash)
+    }
+
+    /// Stores `data`, which must hash to `hash`.
+    fn add_bytes(&self, hash: &str, data: &[u8]) -> io::Result<()> {
+        if self.contains(hash) {
+            return Ok(());
+        }
+        let temp = self.temp_path();
+        fs::write(&temp, data)?;
+        self.insert(temp, hash)
+    }
+
+    fn insert(&self, temp: PathBuf, hash: &str) -> io::Result<()> {
+        let object = match self.object_path(hash) {
+            Ok(object) => object,
+            Err(e) => {
+                fs::remove_file(temp)?;
+                return Err(e);
+            }
+        };
+        if object.exists() {
+            fs::remove_file(temp)?;
+        } else {
+            fs::create_dir_all(object.parent().unwrap())?;
+            fs::rename(temp, object)?;
+        }
+        Ok(())
+    }
+
+    /// Whether every blob `file` is made of is stored.
+    pub fn contains_file(&self, file: &FileNode) -> bool {
+        match file.chunks.is_empty() {
+            true => self.contains(&file.get_hash()),
+            false => file.chunks.iter().all(|chunk| self.contains(&chunk.hash)),
+        }
+    }
+
+    /// Writes the content of `file`, a file of a stored release, to `target`.
+    pub fn copy_file<P: AsRef<Path>>(&self, file: &FileNode, target: P) -> io::Result<()> {
+        if file.chunks.is_empty() {
+            return fs::copy(self.object_path(&file.get_hash())?, target).map(|_| ());
+        }
+        let mut output = File::create(target)?;
+        for chunk in &file.chunks {
+            io::copy(&mut File::open(self.object_path(&chunk.hash)?)?, &mut output)?;
+        }
+        Ok(())
+    }
+
+    fn read_file(&self, file: &FileNode) -> io::Result<Vec<u8>> {
+        if file.chunks.is_empty() {
+            return self.read(&file.get_hash());
+        }
+        let mut content = Vec::new();
+        for chunk in &file.chunks {
+            content.extend(self.read(&chunk.hash)?);
+        }
+        Ok(content)
+    }
+
+    /// Stores every file of `data` not already in the store, reading them from `path`, and saves
+    /// `data` as the release `name`. Returns the number of blobs added.
+    pub fn add_release<P: AsRef<Path>>(&self, name: &str, path: P, data: &FileData) -> io::Result<usize> {
+        let path = path.as_ref();
+        let mut added = 0;
+        if let Some(root) = data.root.as_ref() {
+            for file in root.files() {
+                if self.contains_file(file) {
+                    continue;
+                }
+                log::info!("Storing: {}", file.get_path());
+                let file_path = FileDetail::from_file(file).get_path(path);
+                let unchanged = if file.chunks.is_empty() {
+                    added += 1;
+                    self.add_file(file_path)? == file.get_hash()
+                } else {
+                    let mut chunks = Vec::new();
+                    for chunk in Chunker::new(File::open(file_path)?) {
+                        let (chunk, data) = chunk?;
+                        if !self.contains(&chunk.hash) {
+                            self.add_bytes(&chunk.hash, &data)?;
+                            added += 1;
+                        }
+                        chunks.push(chunk);
+                    }
+                    chunks == file.chunks
+                };
+                if !unchanged {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("File changed since it was scanned: {}", file.get_path())));
+                }
+            }
+        }
+        self.save_manifest(name, data)?;
+        Ok(added)
+    }
+
+    pub fn save_manifest(&self, name: &str, data: &FileData) -> io::Result<()> {
+        Ok(data.save(self.manifest_path(name)?)?)
+    }
+
+    pub fn load_manifest(&self, name: &str) -> io::Result<FileData> {
+        let path = self.manifest_path(name)?;
+        if !path.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No release named {}", name)));
+        }
+        Ok(FileData::load(path)?)
+    }
+
+    /// Lists the names of the stored releases.
+    pub fn manifests(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.root.join(MANIFESTS_DIR))? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(name) = name.strip_suffix(MANIFEST_EXTENSION) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Assembles a bundle upgrading release `from` to release `to` from the stored blobs.
+    ///
+    /// Changed files are stored as deltas against their previous content when that is smaller, or
+    /// as their new chunks when they are chunked in both releases.
+    pub fn build_patch(&self, from: &str, to: &str) -> io::Result<PatchBundle> {
+        let source = self.load_manifest(from)?;
+        let target = self.load_manifest(to)?;
+        let content = ReleaseContent {
+            store: self,
+            source: files_by_path(&source),
+            target: files_by_path(&target),
+        };
+        PatchBundle::build(&content, &source, &target)
+    }
+
+    /// Writes every file and folder of release `name` into `output`, overwriting the ones already
+    /// there. Files in `output` that are not part of the release are left alone.
+    pub fn checkout<P: AsRef<Path>>(&self, name: &str, output: P) -> io::Result<()> {
+        let output = output.as_ref();
+        let data = self.load_manifest(name)?;
+        let Some(root) = data.root.as_ref() else {
+            return Ok(());
+        };
+        let files = files_by_path(&data);
+
+        fs::create_dir_all(output)?;
+        let diffs = DirectoryNode::new(".".to_string(), None).get_update_list(root);
+        apply_diffs(output, &diffs, &BTreeMap::new(), &ApplyOptions::default(), |detail, _, staged| {
+            self.copy_file(files[&detail.to_string()], staged)
+        })
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
+        self.root.join(OBJECTS_DIR).join(format!("{}-{}.tmp", std::process::id(), nanos))
+    }
+
+    fn manifest_path(&self, name: &str) -> io::Result<PathBuf> {
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid release name: {}", name)));
+        }
+        Ok(self.root.join(MANIFESTS_DIR).join(format!("{}{}", name, MANIFEST_EXTENSION)))
+    }
+}
+
+fn files_by_path(data: &FileData) -> HashMap<String, &FileNode> {
+    match data.root.as_ref() {
+        Some(root) => root.files().into_iter().map(|file| (file.get_path(), file)).collect(),
+        None => HashMap::new(),
+    }
+}
+
+/// The files of two stored releases, read back from their blobs.
+struct ReleaseContent<'a> {
+    store: &'a ObjectStore,
+    source: HashMap<String, &'a FileNode>,
+    target: HashMap<String, &'a FileNode>,
+}
+
+impl ContentSource for ReleaseContent<'_> {
+    fn read(&self, detail: &FileDetail, _hash: &str) -> io::Result<Vec<u8>> {
+        let file = self.target.get(&detail.to_string()).ok_or_else(|| not_in_release(detail))?;
+        self.store.read_file(file)
+    }
+
+    fn read_base(&self, detail: &FileDetail, _hash: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(file) = self.source.get(&detail.to_string()) else {
+            return Ok(None);
+        };
+        match self.store.read_file(file) {
+            Ok(old) => Ok(Some(old)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_chunk(&self, _detail: &FileDetail, _hash: &str, chunk: &Chunk) -> io::Result<Vec<u8>> {
+        self.store.read(&chunk.hash)
+    }
+}
+
+fn not_in_release(detail: &FileDetail) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("File is not part of the release: {}", detail))
+}
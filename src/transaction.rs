@@ -0,0 +1,402 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+const JOURNAL_FILE: &str = "journal.bin";
+const STAGING_DIR: &str = "staging";
+const BACKUP_DIR: &str = "backup";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+enum State {
+    Staging,
+    Committing,
+}
+
+#[derive(Deserialize, Serialize)]
+enum Operation {
+    /// Moves `source` into the staging folder to be written elsewhere by a later [`Operation::Write`].
+    Stash { source: PathBuf, staged: PathBuf },
+    /// Moves `target` into `backup`.
+    Remove { target: PathBuf, backup: PathBuf },
+    /// Creates `target` and every missing parent, remembering the ones it created in `created`.
+    CreateDir { target: PathBuf, created: Vec<PathBuf> },
+    /// Moves the existing `target` into `backup` and `staged` into its place.
+    Write { target: PathBuf, staged: PathBuf, backup: PathBuf },
+}
+
+#[derive(Deserialize, Serialize)]
+struct Journal {
+    state: State,
+    operations: Vec<Operation>,
+    completed: usize,
+}
+
+/// Applies a set of changes to an installed tree as a single unit.
+///
+/// New files are staged and replaced files are moved into a backup inside a work folder next to
+/// the installed tree, so every step is a rename. A journal in the work folder records how far the
+/// commit got, letting [`resume`] or [`revert`] finish an apply that was interrupted.
+pub struct Transaction {
+    work_dir: PathBuf,
+    journal: Journal,
+    planned_dirs: HashSet<PathBuf>,
+}
+
+impl Transaction {
+    pub fn begin<P: AsRef<Path>>(install_dir: P) -> io::Result<Self> {
+        let work_dir = work_dir(install_dir.as_ref())?;
+        if work_dir.join(JOURNAL_FILE).exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("An interrupted apply must be resumed or reverted first: {}", work_dir.display())));
+        }
+        if work_dir.exists() {
+            fs::remove_dir_all(&work_dir)?;
+        }
+        fs::create_dir_all(work_dir.join(STAGING_DIR))?;
+        fs::create_dir_all(work_dir.join(BACKUP_DIR))?;
+
+        let transaction = Transaction {
+            work_dir,
+            journal: Journal {
+                state: State::Staging,
+                operations: Vec::new(),
+                completed: 0,
+            },
+            planned_dirs: HashSet::new(),
+        };
+        transaction.save_journal()?;
+        Ok(transaction)
+    }
+
+    /// Returns a fresh path in the staging folder for the content of the next [`Transaction::write`].
+    pub fn stage_path(&self) -> PathBuf {
+        self.work_dir.join(STAGING_DIR).join(self.journal.operations.len().to_string())
+    }
+
+    /// Moves an installed file into the staging folder, returning the path to pass to [`Transaction::write`].
+    pub fn stash<P: AsRef<Path>>(&mut self, source: P) -> PathBuf {
+        let staged = self.stage_path();
+        self.journal.operations.push(Operation::Stash { source: source.as_ref().to_path_buf(), staged: staged.clone() });
+        staged
+    }
+
+    pub fn remove<P: AsRef<Path>>(&mut self, target: P) {
+        let backup = self.work_dir.join(BACKUP_DIR).join(self.journal.operations.len().to_string());
+        self.journal.operations.push(Operation::Remove { target: target.as_ref().to_path_buf(), backup });
+    }
+
+    pub fn create_dir<P: AsRef<Path>>(&mut self, target: P) {
+        let target = target.as_ref().to_path_buf();
+        if self.planned_dirs.insert(target.clone()) {
+            self.journal.operations.push(Operation::CreateDir { target, created: Vec::new() });
+        }
+    }
+
+    /// Replaces `target` with the file previously written to `staged`.
+    pub fn write<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, target: P, staged: Q) {
+        let target = target.as_ref().to_path_buf();
+        if let Some(parent) = target.parent() {
+            self.create_dir(parent);
+        }
+        let backup = self.work_dir.join(BACKUP_DIR).join(self.journal.operations.len().to_string());
+        self.journal.operations.push(Operation::Write { target, staged: staged.as_ref().to_path_buf(), backup });
+    }
+
+    /// Performs every operation, rolling back the ones already done if one fails.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.journal.state = State::Committing;
+        self.save_journal()?;
+        match self.run_forward(false) {
+            Ok(()) => self.finish(),
+            Err(e) => {
+                log::error!("Apply failed, rolling back: {}", e);
+                self.run_backward(false)?;
+                self.finish()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Discards everything staged so far without touching the installed tree.
+    pub fn abort(self) -> io::Result<()> {
+        self.finish()
+    }
+
+    fn load(install_dir: &Path) -> io::Result<Option<Self>> {
+        let work_dir = work_dir(install_dir)?;
+        let bytes = match fs::read(work_dir.join(JOURNAL_FILE)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let journal = bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some(Transaction {
+            work_dir,
+            journal,
+            planned_dirs: HashSet::new(),
+        }))
+    }
+
+    /// Performs the remaining operations. When `resuming`, the first one may already have been
+    /// done before the interruption, so finding its source gone is not an error.
+    fn run_forward(&mut self, resuming: bool) -> io::Result<()> {
+        let start = self.journal.completed;
+        while self.journal.completed < self.journal.operations.len() {
+            let index = self.journal.completed;
+            if let Operation::CreateDir { target, created } = &mut self.journal.operations[index] {
+                // record the folders before creating them so an interruption can still remove them
+                if created.is_empty() {
+                    *created = missing_dirs(target);
+                    self.save_journal()?;
+                }
+            }
+            forward(&self.journal.operations[index], resuming && index == start)?;
+            self.journal.completed += 1;
+            self.save_journal()?;
+        }
+        Ok(())
+    }
+
+    /// Undoes the completed operations. When `interrupted`, the operation at `completed` may have
+    /// finished before the interruption, otherwise it failed before writing anything.
+    fn run_backward(&mut self, interrupted: bool) -> io::Result<()> {
+        // the operation at `completed` may have been interrupted half way, so it is undone as well
+        let end = (self.journal.completed + 1).min(self.journal.operations.len());
+        for (index, operation) in self.journal.operations[..end].iter().enumerate().rev() {
+            backward(operation, interrupted || index < self.journal.completed)?;
+        }
+        self.journal.completed = 0;
+        Ok(())
+    }
+
+    fn save_journal(&self) -> io::Result<()> {
+        let encoded = bincode::serialize(&self.journal).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let temp = self.work_dir.join(format!("{}.tmp", JOURNAL_FILE));
+        let mut file = File::create(&temp)?;
+        file.write_all(&encoded)?;
+        file.sync_all()?;
+        fs::rename(temp, self.work_dir.join(JOURNAL_FILE))
+    }
+
+    fn finish(self) -> io::Result<()> {
+        fs::remove_dir_all(&self.work_dir)
+    }
+}
+
+/// Returns true when an apply to `install_dir` was interrupted and needs [`resume`] or [`revert`].
+pub fn is_interrupted<P: AsRef<Path>>(install_dir: P) -> io::Result<bool> {
+    Ok(work_dir(install_dir.as_ref())?.join(JOURNAL_FILE).exists())
+}
+
+/// Finishes an interrupted apply. One that was still staging is discarded instead.
+pub fn resume<P: AsRef<Path>>(install_dir: P) -> io::Result<()> {
+    let Some(mut transaction) = Transaction::load(install_dir.as_ref())? else {
+        return Ok(());
+    };
+    if transaction.journal.state == State::Committing {
+        log::info!("Resuming interrupted apply at step {}/{}", transaction.journal.completed, transaction.journal.operations.len());
+        transaction.run_forward(true)?;
+    }
+    transaction.finish()
+}
+
+/// Undoes an interrupted apply, restoring the installed tree to its previous state.
+pub fn revert<P: AsRef<Path>>(install_dir: P) -> io::Result<()> {
+    let Some(mut transaction) = Transaction::load(install_dir.as_ref())? else {
+        return Ok(());
+    };
+    if transaction.journal.state == State::Committing {
+        log::info!("Reverting interrupted apply from step {}/{}", transaction.journal.completed, transaction.journal.operations.len());
+        transaction.run_backward(true)?;
+    }
+    transaction.finish()
+}
+
+fn work_dir(install_dir: &Path) -> io::Result<PathBuf> {
+    let install_dir = fs::canonicalize(install_dir)?;
+    match (install_dir.parent(), install_dir.file_name()) {
+        (Some(parent), Some(name)) => Ok(parent.join(format!(".{}.release", name.to_string_lossy()))),
+        _ => Ok(install_dir.join(".release")),
+    }
+}
+
+fn missing_dirs(target: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = target.ancestors().take_while(|dir| !dir.exists()).map(Path::to_path_buf).collect();
+    missing.reverse();
+    missing
+}
+
+fn forward(operation: &Operation, resuming: bool) -> io::Result<()> {
+    match operation {
+        Operation::Stash { source, staged } => {
+            if source.symlink_metadata().is_ok() && staged.symlink_metadata().is_err() {
+                log::info!("Moving: {}", source.display());
+                fs::rename(source, staged)?;
+            } else if !resuming {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("Cannot move missing file: {}", source.display())));
+            } else if staged.symlink_metadata().is_err() {
+                log::warn!("Already moved: {}", source.display());
+            }
+        }
+        Operation::Remove { target, backup } => {
+            if target.symlink_metadata().is_ok() {
+                log::info!("Removing: {}", target.display());
+                fs::rename(target, backup)?;
+            } else {
+                log::warn!("Already removed: {}", target.display());
+            }
+        }
+        Operation::CreateDir { target, .. } => {
+            fs::create_dir_all(target)?;
+        }
+        Operation::Write { target, staged, backup } => {
+            if staged.symlink_metadata().is_err() {
+                if resuming && target.symlink_metadata().is_ok() {
+                    log::warn!("Already written: {}", target.display());
+                    return Ok(());
+                }
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("Nothing staged for {}", target.display())));
+            }
+            if target.symlink_metadata().is_ok() && backup.symlink_metadata().is_err() {
+                fs::rename(target, backup)?;
+            }
+            log::info!("Writing file: {}", target.display());
+            fs::rename(staged, target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes `operation`. `written` tells whether a [`Operation::Write`] may have moved its staged file
+/// into place: only then is a target without a staged file the one the transaction wrote.
+fn backward(operation: &Operation, written: bool) -> io::Result<()> {
+    match operation {
+        Operation::Stash { source, staged } => {
+            if staged.symlink_metadata().is_ok() && source.symlink_metadata().is_err() {
+                fs::rename(staged, source)?;
+            }
+        }
+        Operation::Remove { target, backup } => {
+            if backup.symlink_metadata().is_ok() {
+                fs::rename(backup, target)?;
+            }
+        }
+        Operation::CreateDir { created, .. } => {
+            for dir in created.iter().rev() {
+                if dir.exists() {
+                    if let Err(e) = fs::remove_dir(dir) {
+                        log::warn!("Could not remove {}: {}", dir.display(), e);
+                    }
+                }
+            }
+        }
+        Operation::Write { target, staged, backup } => {
+            if written && staged.symlink_metadata().is_err() && target.symlink_metadata().is_ok() {
+                fs::rename(target, staged)?;
+            }
+            if backup.symlink_metadata().is_ok() {
+                fs::rename(backup, target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::transaction::{forward, is_interrupted, resume, revert, State, Transaction};
+
+    fn install_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("api_release_{}_{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("removed.txt"), "removed").unwrap();
+        fs::write(dir.join("changed.txt"), "old").unwrap();
+        dir
+    }
+
+    fn plan(dir: &PathBuf) -> Transaction {
+        let mut transaction = Transaction::begin(dir).unwrap();
+        transaction.remove(dir.join("removed.txt"));
+        let staged = transaction.stage_path();
+        fs::write(&staged, "new").unwrap();
+        transaction.write(dir.join("changed.txt"), staged);
+        let staged = transaction.stage_path();
+        fs::write(&staged, "added").unwrap();
+        transaction.write(dir.join("sub").join("added.txt"), staged);
+        transaction
+    }
+
+    fn interrupt(mut transaction: Transaction, steps: usize) {
+        transaction.journal.state = State::Committing;
+        for operation in &transaction.journal.operations[..steps] {
+            forward(operation, false).unwrap();
+        }
+        transaction.journal.completed = steps;
+        transaction.save_journal().unwrap();
+    }
+
+    #[test]
+    fn test_rollback_on_failure() {
+        let dir = install_dir("transaction_rollback");
+        let mut transaction = plan(&dir);
+        transaction.create_dir(dir.join("changed.txt").join("sub"));
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(fs::read_to_string(dir.join("removed.txt")).unwrap(), "removed");
+        assert_eq!(fs::read_to_string(dir.join("changed.txt")).unwrap(), "old");
+        assert!(!dir.join("sub").exists());
+        assert!(!is_interrupted(&dir).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_staged_file() {
+        let dir = install_dir("transaction_missing_staged");
+        let mut transaction = Transaction::begin(&dir).unwrap();
+        transaction.remove(dir.join("removed.txt"));
+        let staged = transaction.stage_path();
+        transaction.write(dir.join("changed.txt"), staged);
+        assert!(transaction.commit().is_err());
+
+        // the rollback must not take the untouched file for one the transaction wrote
+        assert_eq!(fs::read_to_string(dir.join("removed.txt")).unwrap(), "removed");
+        assert_eq!(fs::read_to_string(dir.join("changed.txt")).unwrap(), "old");
+
+        let mut transaction = Transaction::begin(&dir).unwrap();
+        let staged = transaction.stash(dir.join("missing.txt"));
+        transaction.write(dir.join("sub").join("moved.txt"), staged);
+        assert!(transaction.commit().is_err());
+        assert!(!dir.join("sub").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resume_and_revert() {
+        let dir = install_dir("transaction_resume");
+        interrupt(plan(&dir), 2);
+        assert!(is_interrupted(&dir).unwrap());
+        assert!(Transaction::begin(&dir).is_err());
+        resume(&dir).unwrap();
+        assert!(!dir.join("removed.txt").exists());
+        assert_eq!(fs::read_to_string(dir.join("changed.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("sub").join("added.txt")).unwrap(), "added");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let dir = install_dir("transaction_revert");
+        interrupt(plan(&dir), 3);
+        revert(&dir).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("removed.txt")).unwrap(), "removed");
+        assert_eq!(fs::read_to_string(dir.join("changed.txt")).unwrap(), "old");
+        assert!(!dir.join("sub").exists());
+        assert!(!is_interrupted(&dir).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::data::FileData;
+use crate::fs::{file_name, join_path};
+use crate::hash::calculate_file_hash;
+
+/// Differences between an installed tree and the file data it should match.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub missing: Vec<String>,
+    pub extra: Vec<String>,
+    pub corrupt: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.corrupt.is_empty()
+    }
+}
+
+/// Rehashes every file of `data` under `install_dir` and reports missing, extra and corrupt files.
+#[cfg(feature = "async")]
+pub async fn verify<P: AsRef<Path>>(install_dir: P, data: &FileData) -> io::Result<VerifyReport> {
+    let (mut report, existing) = compare(install_dir.as_ref(), data)?;
+    for file in existing {
+        if calculate_file_hash(&file.path).await? != file.hash {
+            report.corrupt.push(file.relative);
+        }
+    }
+    Ok(report)
+}
+
+/// Rehashes every file of `data` under `install_dir` and reports missing, extra and corrupt files.
+#[cfg(not(feature = "async"))]
+pub fn verify<P: AsRef<Path>>(install_dir: P, data: &FileData) -> io::Result<VerifyReport> {
+    let (mut report, existing) = compare(install_dir.as_ref(), data)?;
+    for file in existing {
+        if calculate_file_hash(&file.path)? != file.hash {
+            report.corrupt.push(file.relative);
+        }
+    }
+    Ok(report)
+}
+
+struct ExistingFile {
+    relative: String,
+    path: PathBuf,
+    hash: String,
+}
+
+/// Fills in missing and extra files, and corrupt files whose size is off, returning the other files
+/// present on both sides to be hashed.
+fn compare(install_dir: &Path, data: &FileData) -> io::Result<(VerifyReport, Vec<ExistingFile>)> {
+    let mut expected: BTreeMap<String, (String, Option<u64>)> = match data.root.as_ref() {
+        Some(root) => root.files().into_iter().map(|file| (file.get_path(), (file.get_hash(), file.size))).collect(),
+        None => BTreeMap::new(),
+    };
+
+    let mut report = VerifyReport::default();
+    let mut existing = Vec::new();
+    for (relative, path) in list_files(install_dir, ".")? {
+        match expected.remove(&relative) {
+            Some((_, Some(size))) if fs::metadata(&path)?.len() != size => report.corrupt.push(relative),
+            Some((hash, _)) => existing.push(ExistingFile { relative, path, hash }),
+            None => report.extra.push(relative),
+        }
+    }
+    report.missing = expected.into_keys().collect();
+    Ok((report, existing))
+}
+
+fn list_files(path: &Path, relative_path: &str) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        let name = file_name(&path)?;
+        let relative = join_path(relative_path, &name);
+        if path.is_dir() {
+            files.extend(list_files(&path, &relative)?);
+        } else {
+            files.push((relative, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
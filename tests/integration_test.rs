@@ -0,0 +1,971 @@
+#![cfg(not(feature = "async"))]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use api_release::archive::{apply_archive, apply_archive_with_options, extract_archive, write_archive, ArchiveFormat};
+use api_release::bundle::{PatchBundle, PatchManifest};
+use api_release::chunk::add_chunks;
+use api_release::data::{FileData, ReleaseMetadata, TextFormat};
+use api_release::error::Error;
+use api_release::fs::{generate_file_data_from_path, generate_file_data_with_rules};
+use api_release::node::diff::{DiffSummary, FileDetail, FileDiff};
+use api_release::patch::{apply_patch, generate_patch, ApplyOptions};
+use api_release::repair::{repair, RepairSource};
+use api_release::report::{write_diffs, DiffFormat, DiffRecord, DiffStatus};
+use api_release::repository::Repository;
+use api_release::rules::RuleSet;
+use api_release::signature::InstallSignature;
+use api_release::store::ObjectStore;
+use api_release::verify::verify;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use semver::Version;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("api_release_{}_{}", name, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    fs::write(path, content).unwrap();
+}
+
+#[test]
+fn test_apply_patch() {
+    let base = temp_dir("apply_patch");
+    let old = base.join("old");
+    let new = base.join("new");
+    let patch = base.join("patch");
+
+    write(&old.join("same.txt"), "same");
+    write(&old.join("changed.txt"), "old");
+    write(&old.join("removed.txt"), "removed");
+    write(&old.join("gone").join("file.txt"), "gone");
+    write(&new.join("same.txt"), "same");
+    write(&new.join("changed.txt"), "new");
+    write(&new.join("added").join("nested").join("file.txt"), "added");
+    fs::create_dir_all(new.join("empty")).unwrap();
+
+    let source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+    let diffs = source.diff(&target).unwrap();
+
+    generate_patch(&new, &patch, &diffs).unwrap();
+    apply_patch(&patch, &old, &diffs).unwrap();
+
+    assert_eq!(fs::read_to_string(old.join("same.txt")).unwrap(), "same");
+    assert_eq!(fs::read_to_string(old.join("changed.txt")).unwrap(), "new");
+    assert_eq!(fs::read_to_string(old.join("added").join("nested").join("file.txt")).unwrap(), "added");
+    assert!(old.join("empty").is_dir());
+    assert!(!old.join("removed.txt").exists());
+    assert!(!old.join("gone").exists());
+
+    let applied = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    assert!(applied.diff(&target).unwrap().is_empty());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_patch_bundle() {
+    let base = temp_dir("patch_bundle");
+    let old = base.join("old");
+    let new = base.join("new");
+    let bundle_path = base.join("patch.bin.gz");
+
+    write(&old.join("changed.txt"), "old");
+    write(&old.join("removed.txt"), "removed");
+    write(&new.join("changed.txt"), "new");
+    write(&new.join("sub").join("added.txt"), "added");
+
+    let source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+
+    PatchBundle::create(&new, &source, &target).unwrap().save(&bundle_path).unwrap();
+    let bundle = PatchBundle::load(&bundle_path).unwrap();
+    assert!(bundle.manifest.applies_to(&source));
+    assert_eq!(bundle.manifest.hashes.len(), 2);
+    bundle.apply(&old).unwrap();
+
+    let applied = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    assert!(applied.diff(&target).unwrap().is_empty());
+
+    let mut corrupt = PatchBundle::load(&bundle_path).unwrap();
+    corrupt.payload.values_mut().for_each(|content| content.push(0));
+    assert!(corrupt.verify().is_err());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+fn archive_round_trip(name: &str, file_name: &str) {
+    let base = temp_dir(name);
+    let old = base.join("old");
+    let new = base.join("new");
+    let archive = base.join(file_name);
+
+    write(&old.join("changed.txt"), "old");
+    write(&old.join("removed.txt"), "removed");
+    write(&new.join("changed.txt"), "new");
+    write(&new.join("sub").join("added.txt"), "added");
+
+    let source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+
+    let format = ArchiveFormat::from_path(&archive).unwrap();
+    write_archive(&new, &PatchManifest::new(&source, &target).unwrap(), &archive, format).unwrap();
+    let manifest = apply_archive(&archive, &old, format).unwrap();
+    assert_eq!(manifest.hashes.len(), 2);
+
+    let applied = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    assert!(applied.diff(&target).unwrap().is_empty());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_patch_archive() {
+    archive_round_trip("patch_archive", "patch.tar.gz");
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_patch_archive_zip() {
+    archive_round_trip("patch_archive_zip", "patch.zip");
+}
+
+#[test]
+fn test_manifest_path_escape() {
+    let base = temp_dir("manifest_path_escape");
+    let new = base.join("new");
+    let out = base.join("x").join("y").join("out");
+    let archive = base.join("patch.tar.gz");
+
+    write(&new.join("evil.txt"), "evil");
+    fs::create_dir_all(new.join("a").join("b")).unwrap();
+    fs::create_dir_all(&out).unwrap();
+    let source = generate_file_data_from_path(&out, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+
+    // point the added file two levels above the folder it is written to
+    let mut bundle = PatchBundle::create(&new, &source, &target).unwrap();
+    let key = format!(".{}evil.txt", std::path::MAIN_SEPARATOR);
+    let evil = FileDetail::new(Arc::from(format!(".{0}..{0}..", std::path::MAIN_SEPARATOR)), "evil.txt".to_string(), true);
+    let hash = bundle.manifest.hashes.remove(&key).unwrap();
+    let content = bundle.payload.remove(&key).unwrap();
+    bundle.manifest.hashes.insert(evil.to_string(), hash);
+    bundle.payload.insert(evil.to_string(), content);
+    bundle.manifest.diffs = vec![FileDiff::Add(evil)];
+
+    write_archive(new.join("a").join("b"), &bundle.manifest, &archive, ArchiveFormat::TarGz).unwrap();
+    assert!(extract_archive(&archive, &out, ArchiveFormat::TarGz).is_err());
+    assert!(apply_archive(&archive, &out, ArchiveFormat::TarGz).is_err());
+    assert!(bundle.apply(&out).is_err());
+    assert!(!base.join("x").join("evil.txt").exists());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_patch_bundle_delta() {
+    let base = temp_dir("patch_bundle_delta");
+    let old = base.join("old");
+    let new = base.join("new");
+    let install = base.join("install");
+
+    let content: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+    write(&old.join("large.txt"), &content);
+    write(&old.join("small.txt"), "old");
+    write(&new.join("large.txt"), &content.replace("line 10000\n", "changed line\n"));
+    write(&new.join("small.txt"), "new");
+    for name in ["large.txt", "small.txt"] {
+        write(&install.join(name), &fs::read_to_string(old.join(name)).unwrap());
+    }
+
+    let source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+
+    let bundle = PatchBundle::create_with_delta(&new, &old, &source, &target).unwrap();
+    assert_eq!(bundle.manifest.deltas.len(), 1);
+    assert!(bundle.payload.values().map(Vec::len).sum::<usize>() < content.len() / 10);
+    bundle.apply(&install).unwrap();
+
+    let applied = generate_file_data_from_path(&install, &Vec::new()).unwrap();
+    assert!(applied.diff(&target).unwrap().is_empty());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_verify() {
+    let base = temp_dir("verify");
+    write(&base.join("same.txt"), "same");
+    write(&base.join("sub").join("corrupt.txt"), "original");
+    write(&base.join("missing.txt"), "missing");
+
+    let data = generate_file_data_from_path(&base, &Vec::new()).unwrap();
+    assert!(verify(&base, &data).unwrap().is_ok());
+
+    fs::remove_file(base.join("missing.txt")).unwrap();
+    write(&base.join("sub").join("corrupt.txt"), "modified");
+    write(&base.join("extra.txt"), "extra");
+
+    let report = verify(&base, &data).unwrap();
+    let sep = std::path::MAIN_SEPARATOR;
+    assert_eq!(report.missing, vec![format!(".{}missing.txt", sep)]);
+    assert_eq!(report.extra, vec![format!(".{}extra.txt", sep)]);
+    assert_eq!(report.corrupt, vec![format!(".{}sub{}corrupt.txt", sep, sep)]);
+
+    // an installed name that is not UTF-8 is an error, not a panic
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        fs::write(base.join(OsStr::from_bytes(b"bad\xff.txt")), "bad").unwrap();
+        assert_eq!(verify(&base, &data).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_repair() {
+    let base = temp_dir("repair");
+    let release = base.join("release");
+    let install = base.join("install");
+
+    for dir in [&release, &install] {
+        write(&dir.join("same.txt"), "same");
+        write(&dir.join("sub").join("corrupt.txt"), "original");
+        write(&dir.join("sub").join("missing.txt"), "missing");
+    }
+    let data = generate_file_data_from_path(&release, &Vec::new()).unwrap();
+
+    fs::remove_dir_all(install.join("sub")).unwrap();
+    write(&install.join("sub").join("corrupt.txt"), "modified");
+    write(&install.join("extra.txt"), "extra");
+
+    let report = repair(&install, RepairSource::Directory(&release), &data).unwrap();
+    assert_eq!(report.repaired.len(), 2);
+    assert!(report.unavailable.is_empty());
+
+    let report = verify(&install, &data).unwrap();
+    assert!(report.missing.is_empty() && report.corrupt.is_empty());
+    assert_eq!(report.extra.len(), 1);
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_rename_detection() {
+    let base = temp_dir("rename_detection");
+    let old = base.join("old");
+    let new = base.join("new");
+
+    write(&old.join("moved").join("file.txt"), "moved out of a removed folder");
+    write(&old.join("renamed.txt"), "renamed");
+    write(&old.join("copied.txt"), "copied");
+    write(&new.join("target").join("file.txt"), "moved out of a removed folder");
+    write(&new.join("target").join("renamed.txt"), "renamed");
+    write(&new.join("copied.txt"), "copied");
+    write(&new.join("copy.txt"), "copied");
+
+    let source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+    let diffs = source.diff(&target).unwrap();
+
+    let renames = diffs.iter().filter(|diff| matches!(diff, FileDiff::Rename { .. })).count();
+    let added_files = diffs.iter().filter(|diff| matches!(diff, FileDiff::Add(detail) if detail.is_file)).count();
+    assert_eq!(renames, 2);
+    assert_eq!(added_files, 1);
+
+    let bundle = PatchBundle::create(&new, &source, &target).unwrap();
+    assert_eq!(bundle.payload.len(), 1);
+    bundle.apply(&old).unwrap();
+
+    let applied = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    assert!(applied.diff(&target).unwrap().is_empty());
+    assert!(!old.join("moved").exists());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_rename_missing_source() {
+    let base = temp_dir("rename_missing_source");
+    let old = base.join("old");
+    let new = base.join("new");
+
+    write(&old.join("a.txt"), "renamed");
+    write(&old.join("kept.txt"), "kept");
+    write(&new.join("sub").join("b.txt"), "renamed");
+    write(&new.join("kept.txt"), "kept");
+
+    let source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+    let bundle = PatchBundle::create(&new, &source, &target).unwrap();
+    assert!(bundle.manifest.diffs.iter().any(|diff| matches!(diff, FileDiff::Rename { .. })));
+
+    fs::remove_file(old.join("a.txt")).unwrap();
+    assert!(bundle.apply(&old).is_err());
+    assert!(!old.join("sub").exists());
+    assert_eq!(fs::read_to_string(old.join("kept.txt")).unwrap(), "kept");
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_duplicate_content() {
+    let base = temp_dir("duplicate_content");
+    let old = base.join("old");
+    let new = base.join("new");
+    let archive = base.join("patch.tar.gz");
+
+    fs::create_dir_all(&old).unwrap();
+    for name in ["en", "fr", "de"] {
+        write(&new.join(name).join("texture.bin"), "shared texture");
+    }
+    write(&new.join("unique.txt"), "unique");
+
+    let source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+
+    let bundle = PatchBundle::create(&new, &source, &target).unwrap();
+    assert_eq!(bundle.manifest.duplicates.len(), 2);
+    assert_eq!(bundle.payload.len(), 2);
+
+    let linked = base.join("linked");
+    fs::create_dir_all(&linked).unwrap();
+    bundle.apply_with_options(&linked, &ApplyOptions { hardlink: true }).unwrap();
+    assert!(generate_file_data_from_path(&linked, &Vec::new()).unwrap().diff(&target).unwrap().is_empty());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let en = fs::metadata(linked.join("en").join("texture.bin")).unwrap();
+        let fr = fs::metadata(linked.join("fr").join("texture.bin")).unwrap();
+        assert_eq!(en.ino(), fr.ino());
+    }
+
+    write_archive(&new, &bundle.manifest, &archive, ArchiveFormat::TarGz).unwrap();
+    apply_archive_with_options(&archive, &old, ArchiveFormat::TarGz, &ApplyOptions::default()).unwrap();
+    assert!(generate_file_data_from_path(&old, &Vec::new()).unwrap().diff(&target).unwrap().is_empty());
+
+    // a duplicate listed before its original has no payload of its own
+    let mut bundle = bundle;
+    let original = bundle.manifest.duplicates.values().next().unwrap().clone();
+    let index = bundle.manifest.diffs.iter().position(|diff| matches!(diff, FileDiff::Add(detail) if detail.to_string() == original)).unwrap();
+    let diff = bundle.manifest.diffs.remove(index);
+    bundle.manifest.diffs.push(diff);
+    let crafted = base.join("crafted");
+    fs::create_dir_all(&crafted).unwrap();
+    assert!(bundle.apply(&crafted).is_err());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_object_store() {
+    let base = temp_dir("object_store");
+    let v1 = base.join("v1");
+    let v2 = base.join("v2");
+    let installed = base.join("installed");
+
+    write(&v1.join("same.txt"), "same");
+    write(&v1.join("changed.txt"), "old");
+    write(&v2.join("same.txt"), "same");
+    write(&v2.join("changed.txt"), "new");
+    write(&v2.join("empty").join("added.txt"), "added");
+    fs::create_dir_all(v2.join("folder")).unwrap();
+
+    let source = generate_file_data_from_path(&v1, &Vec::new()).unwrap();
+    let target = generate_file_data_from_path(&v2, &Vec::new()).unwrap();
+
+    let store = ObjectStore::open(base.join("store")).unwrap();
+    assert_eq!(store.add_release("v1", &v1, &source).unwrap(), 2);
+    assert_eq!(store.add_release("v2", &v2, &target).unwrap(), 2);
+    assert_eq!(store.manifests().unwrap(), vec!["v1", "v2"]);
+    assert!(store.load_manifest("v3").is_err());
+    // hashes name stored blobs, so one that is not a hash cannot reach outside the store
+    assert!(!store.contains("../../store/manifests/v1"));
+    assert_eq!(store.read("../../store/manifests/v1").unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+    store.checkout("v1", &installed).unwrap();
+    assert!(generate_file_data_from_path(&installed, &Vec::new()).unwrap().diff(&source).unwrap().is_empty());
+
+    store.build_patch("v1", "v2").unwrap().apply(&installed).unwrap();
+    assert!(generate_file_data_from_path(&installed, &Vec::new()).unwrap().diff(&target).unwrap().is_empty());
+    assert!(installed.join("folder").is_dir());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+fn random_bytes(len: usize, mut seed: u64) -> Vec<u8> {
+    (0..len).map(|_| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as u8
+    }).collect()
+}
+
+#[test]
+fn test_chunked_patch() {
+    let base = temp_dir("chunked_patch");
+    let old = base.join("old");
+    let new = base.join("new");
+
+    let content = random_bytes(2 * 1024 * 1024, 3);
+    let mut changed = content.clone();
+    changed[1024 * 1024..1024 * 1024 + 16].copy_from_slice(b"changed in place");
+    write(&old.join("small.txt"), "small");
+    fs::write(old.join("large.bin"), &content).unwrap();
+    write(&new.join("small.txt"), "small changed");
+    fs::write(new.join("large.bin"), &changed).unwrap();
+
+    let mut source = generate_file_data_from_path(&old, &Vec::new()).unwrap();
+    let mut target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+    assert_eq!(add_chunks(&mut source, &old, 1024 * 1024).unwrap(), 1);
+    assert_eq!(add_chunks(&mut target, &new, 1024 * 1024).unwrap(), 1);
+
+    let bundle = PatchBundle::create(&new, &source, &target).unwrap();
+    assert!(bundle.manifest.chunked.contains_key(&format!(".{}large.bin", std::path::MAIN_SEPARATOR)));
+    assert_eq!(bundle.payload.len(), 1);
+    assert!(bundle.chunks.values().map(Vec::len).sum::<usize>() < content.len() / 4);
+
+    let store = ObjectStore::open(base.join("store")).unwrap();
+    let first = store.add_release("v1", &old, &source).unwrap();
+    let second = store.add_release("v2", &new, &target).unwrap();
+    assert!(second < first);
+
+    let installed = base.join("installed");
+    store.checkout("v1", &installed).unwrap();
+    store.build_patch("v1", "v2").unwrap().apply(&installed).unwrap();
+    assert!(generate_file_data_from_path(&installed, &Vec::new()).unwrap().diff(&target).unwrap().is_empty());
+
+    bundle.apply(&old).unwrap();
+    assert_eq!(fs::read(old.join("large.bin")).unwrap(), changed);
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_patch_against_install() {
+    let base = temp_dir("patch_against_install");
+    let installed = base.join("installed");
+    let new = base.join("new");
+    let signature_file = base.join("signature.bin.gz");
+
+    // an install that matches no release: an older game file and a locally edited config
+    let mut game = random_bytes(256 * 1024, 4);
+    fs::create_dir_all(&installed).unwrap();
+    fs::create_dir_all(&new).unwrap();
+    fs::write(installed.join("game.bin"), &game).unwrap();
+    write(&installed.join("config.txt"), "locally edited");
+    write(&installed.join("local.txt"), "not part of the release");
+
+    game[1000..1010].copy_from_slice(b"new header");
+    fs::write(new.join("game.bin"), &game).unwrap();
+    write(&new.join("config.txt"), "release config");
+
+    InstallSignature::scan(&installed, &Vec::new()).unwrap().save(&signature_file).unwrap();
+    let signature = InstallSignature::load(&signature_file).unwrap();
+    let target = generate_file_data_from_path(&new, &Vec::new()).unwrap();
+
+    let bundle = PatchBundle::create_for_install(&new, &signature, &target).unwrap();
+    let game_key = format!(".{}game.bin", std::path::MAIN_SEPARATOR);
+    assert!(bundle.manifest.deltas.contains_key(&game_key));
+    assert!(bundle.payload[&game_key].len() < game.len() / 4);
+
+    bundle.apply(&installed).unwrap();
+    assert!(generate_file_data_from_path(&installed, &Vec::new()).unwrap().diff(&target).unwrap().is_empty());
+    assert!(!installed.join("local.txt").exists());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_repository() {
+    let base = temp_dir("repository");
+    let release = base.join("release");
+    let installed = base.join("installed");
+    let repository = Repository::open(base.join("repository")).unwrap();
+
+    let big = random_bytes(64 * 1024, 5);
+    for (i, content) in ["one", "two", "three"].iter().enumerate() {
+        write(&release.join("version.txt"), content);
+        // version 2 adds a large file that version 3 removes again
+        if i == 1 {
+            fs::write(release.join("big.bin"), &big).unwrap();
+        } else if release.join("big.bin").exists() {
+            fs::remove_file(release.join("big.bin")).unwrap();
+        }
+        let data = generate_file_data_from_path(&release, &Vec::new()).unwrap();
+        assert_eq!(repository.add_release(&release, data).unwrap(), i as u64 + 1);
+    }
+    assert_eq!(repository.versions().unwrap(), vec![1, 2, 3]);
+    assert_eq!(repository.patches().unwrap(), vec![(1, 2), (2, 3)]);
+
+    // planning only reads the stored patches
+    assert_eq!(repository.update_path(1, 3).unwrap().len(), 2);
+    assert_eq!(repository.patches().unwrap(), vec![(1, 2), (2, 3)]);
+    assert!(repository.update_path(3, 1).is_err());
+
+    repository.store().checkout("1", &installed).unwrap();
+    assert_eq!(repository.update(&installed, 1).unwrap(), 3);
+    assert!(generate_file_data_from_path(&installed, &Vec::new()).unwrap().diff(&repository.manifest(3).unwrap()).unwrap().is_empty());
+    assert!(repository.patches().unwrap().contains(&(1, 3)));
+    assert_eq!(repository.update_path(1, 3).unwrap().len(), 1);
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_repository_cheaper_chain() {
+    let base = temp_dir("repository_chain");
+    let release = base.join("release");
+    let installed = base.join("installed");
+    let repository = Repository::open(base.join("repository")).unwrap();
+
+    for content in ["one", "two", "three"] {
+        write(&release.join("version.txt"), content);
+        repository.add_release(&release, generate_file_data_from_path(&release, &Vec::new()).unwrap()).unwrap();
+    }
+
+    // a direct patch larger than both incremental patches together loses to them
+    let direct = repository.build_direct_patch(1, 3).unwrap();
+    let chain_size: u64 = repository.update_path(1, 2).unwrap().iter()
+        .chain(repository.update_path(2, 3).unwrap().iter())
+        .map(|patch| fs::metadata(patch).unwrap().len())
+        .sum();
+    let mut padded = fs::read(&direct).unwrap();
+    padded.resize(chain_size as usize + 1, 0);
+    fs::write(&direct, padded).unwrap();
+
+    let path = repository.update_path(1, 3).unwrap();
+    assert_eq!(path.len(), 2);
+    assert!(!path.contains(&direct));
+
+    repository.store().checkout("1", &installed).unwrap();
+    assert_eq!(repository.update(&installed, 1).unwrap(), 3);
+    assert_eq!(fs::read_to_string(installed.join("version.txt")).unwrap(), "three");
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_update_failure() {
+    let base = temp_dir("update_failure");
+    let release = base.join("release");
+    let installed = base.join("installed");
+    let repository = Repository::open(base.join("repository")).unwrap();
+
+    write(&release.join("a.txt"), "renamed");
+    repository.add_release(&release, generate_file_data_from_path(&release, &Vec::new()).unwrap()).unwrap();
+    fs::rename(release.join("a.txt"), release.join("b.txt")).unwrap();
+    repository.add_release(&release, generate_file_data_from_path(&release, &Vec::new()).unwrap()).unwrap();
+
+    repository.store().checkout("1", &installed).unwrap();
+    fs::remove_file(installed.join("a.txt")).unwrap();
+    let error = repository.update(&installed, 1).unwrap_err();
+    assert!(error.to_string().starts_with("Update stopped at version 1: "));
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_channels() {
+    let base = temp_dir("channels");
+    let release = base.join("release");
+    let installed = base.join("installed");
+    let repository = Repository::open(base.join("repository")).unwrap();
+
+    for content in ["one", "two"] {
+        write(&release.join("version.txt"), content);
+        let data = generate_file_data_from_path(&release, &Vec::new()).unwrap();
+        repository.add_release(&release, data).unwrap();
+    }
+    repository.set_channel("stable", 1).unwrap();
+    repository.set_channel("beta", 2).unwrap();
+    assert!(repository.set_channel("nightly", 3).is_err());
+    assert!(repository.promote("nightly", "beta").is_err());
+
+    repository.store().checkout("1", &installed).unwrap();
+    assert_eq!(repository.update_channel(&installed, 1, "stable").unwrap(), 1);
+    assert_eq!(fs::read_to_string(installed.join("version.txt")).unwrap(), "one");
+
+    assert_eq!(repository.promote("beta", "stable").unwrap(), 2);
+    assert_eq!(repository.channel("stable").unwrap(), Some(2));
+    assert_eq!(repository.update_channel(&installed, 1, "stable").unwrap(), 2);
+    assert_eq!(fs::read_to_string(installed.join("version.txt")).unwrap(), "two");
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_release_metadata() {
+    let base = temp_dir("release_metadata");
+    let release = base.join("release");
+    let repository = Repository::open(base.join("repository")).unwrap();
+    write(&release.join("version.txt"), "one");
+
+    let mut data = generate_file_data_from_path(&release, &Vec::new()).unwrap();
+    data.metadata = ReleaseMetadata {
+        semver: Some(Version::new(1, 2, 3)),
+        notes: Some("First release".to_string()),
+        labels: [("platform".to_string(), "linux".to_string())].into_iter().collect(),
+        min_updater_version: Some(Version::new(99, 0, 0)),
+        build_id: Some("build-42".to_string()),
+    };
+    let expected = data.metadata.clone();
+    data.save(base.join("data.bin.gz")).unwrap();
+    assert_eq!(FileData::load(base.join("data.bin.gz")).unwrap().metadata, expected);
+
+    let version = repository.add_release(&release, data).unwrap();
+    assert_eq!(repository.manifest(version).unwrap().metadata, expected);
+    assert!(!expected.supports_updater(&Version::new(1, 0, 0)));
+    // the release needs a newer updater than this one
+    let installed = base.join("installed");
+    fs::create_dir_all(&installed).unwrap();
+    assert!(repository.update(&installed, 0).is_err());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_load_errors() {
+    let base = temp_dir("load_errors");
+
+    assert!(matches!(FileData::load(base.join("missing.bin.gz")), Err(Error::NotFound(_))));
+
+    fs::write(base.join("corrupt.bin.gz"), "not gzip").unwrap();
+    assert!(matches!(FileData::load(base.join("corrupt.bin.gz")), Err(Error::CorruptCompression(_))));
+
+    let mut encoder = GzEncoder::new(fs::File::create(base.join("future.bin.gz")).unwrap(), Compression::default());
+    encoder.write_all(b"RLFD").unwrap();
+    encoder.write_all(&99u32.to_le_bytes()).unwrap();
+    encoder.finish().unwrap();
+    assert!(matches!(FileData::load(base.join("future.bin.gz")), Err(Error::UnsupportedVersion(99))));
+
+    let scanned = generate_file_data_from_path(&base, &Vec::new()).unwrap();
+    assert!(matches!(FileData::default().diff(&scanned), Err(Error::EmptyRoot)));
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_text_export() {
+    let base = temp_dir("text_export");
+    let release = base.join("release");
+    write(&release.join("a.txt"), "a");
+    write(&release.join("sub").join("b.txt"), "b");
+    fs::write(release.join("large.bin"), random_bytes(512 * 1024, 6)).unwrap();
+
+    let mut data = generate_file_data_from_path(&release, &Vec::new()).unwrap();
+    add_chunks(&mut data, &release, 256 * 1024).unwrap();
+    data.metadata.semver = Some(Version::new(2, 0, 0));
+    data.metadata.labels.insert("platform".to_string(), "linux".to_string());
+    data.save(base.join("data.bin.gz")).unwrap();
+    let binary = fs::read(base.join("data.bin.gz")).unwrap();
+
+    for (name, format) in [("data.json", TextFormat::Json), ("data.toml", TextFormat::Toml)] {
+        assert_eq!(TextFormat::from_path(name), Some(format));
+        data.export(base.join(name), format).unwrap();
+        let imported = FileData::import(base.join(name), format).unwrap();
+        assert!(imported.diff(&data).unwrap().is_empty());
+        imported.save(base.join("imported.bin.gz")).unwrap();
+        assert_eq!(fs::read(base.join("imported.bin.gz")).unwrap(), binary);
+    }
+
+    // hand-edited manifests may leave out the chunk lists, but not break a hash
+    let mut value: serde_json::Value = serde_json::from_str(&data.to_text(TextFormat::Json).unwrap()).unwrap();
+    remove_empty_chunks(&mut value);
+    let text = value.to_string();
+    assert!(!text.contains("\"chunks\":[]"));
+    assert!(FileData::from_text(&text, TextFormat::Json).unwrap().diff(&data).unwrap().is_empty());
+    let hash = data.root.as_ref().unwrap().files().iter().find(|file| file.name == "a.txt").unwrap().get_hash();
+    assert!(FileData::from_text(&text.replace(&hash, &hash[..60]), TextFormat::Json).is_err());
+    assert!(FileData::from_text(&text.replace(&hash, &"g".repeat(64)), TextFormat::Json).is_err());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+fn remove_empty_chunks(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            if map.get("chunks").is_some_and(|chunks| chunks.as_array().is_some_and(Vec::is_empty)) {
+                map.remove("chunks");
+            }
+            map.values_mut().for_each(remove_empty_chunks);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(remove_empty_chunks),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_file_sizes() {
+    let base = temp_dir("sizes");
+    let source = base.join("source");
+    let target = base.join("target");
+    write(&source.join("same.txt"), "same");
+    write(&source.join("grown.txt"), "small");
+    write(&target.join("same.txt"), "same");
+    write(&target.join("grown.txt"), "larger content");
+    write(&target.join("sub").join("new.txt"), "new file");
+
+    let source_data = generate_file_data_from_path(&source, &Vec::new()).unwrap();
+    let target_data = generate_file_data_from_path(&target, &Vec::new()).unwrap();
+    assert_eq!(target_data.root.as_ref().unwrap().files()[0].size, Some(14));
+
+    let diffs = source_data.diff(&target_data).unwrap();
+    let summary = DiffSummary::new(&diffs);
+    assert_eq!(summary, DiffSummary { files: 2, bytes: 14 + 8 });
+    assert_eq!(summary.to_string(), "2 files / 0.0 MB");
+
+    // same length, so only the hash can tell
+    write(&target.join("same.txt"), "SAME");
+    assert_eq!(verify(&target, &target_data).unwrap().corrupt.len(), 1);
+    // a different length is caught before hashing
+    write(&target.join("same.txt"), "longer");
+    assert_eq!(verify(&target, &target_data).unwrap().corrupt.len(), 1);
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_ignore_patterns() {
+    let base = temp_dir("ignore");
+    write(&base.join(".releaseignore"), "# build output\n*.pdb\n**/*.log\n!keep.log\n/logs/\n");
+    write(&base.join("app.exe"), "app");
+    write(&base.join("app.pdb"), "symbols");
+    write(&base.join("sub").join("debug.log"), "log");
+    write(&base.join("sub").join("keep.log"), "kept");
+    write(&base.join("logs").join("a.txt"), "log dir");
+    write(&base.join("sub").join("logs").join("b.txt"), "not anchored");
+    write(&base.join("tmp").join("c.txt"), "tmp");
+
+    let data = generate_file_data_from_path(&base, &["tmp/".to_string(), "!app.pdb".to_string()]).unwrap();
+    let sep = std::path::MAIN_SEPARATOR;
+    let paths: Vec<String> = data.root.as_ref().unwrap().files().iter().map(|file| file.get_path()).collect();
+    assert_eq!(paths, vec![
+        format!(".{}.releaseignore", sep),
+        format!(".{}app.exe", sep),
+        format!(".{}app.pdb", sep),
+        format!(".{}sub{}keep.log", sep, sep),
+        format!(".{}sub{}logs{}b.txt", sep, sep, sep),
+    ]);
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_include_rules() {
+    let base = temp_dir("include");
+    let build = base.join("build");
+    write(&build.join("bin").join("app.exe"), "app");
+    write(&build.join("bin").join("app.pdb"), "symbols");
+    write(&build.join("data").join("maps").join("a.pak"), "pak");
+    write(&build.join("data").join("maps").join("a.src"), "source");
+    write(&build.join("data").join("raw").join("b.src"), "source");
+    write(&build.join("config").join("app.toml"), "config");
+    write(&build.join("obj").join("main.o"), "object");
+    write(&base.join("scan.rules"), "# shipped content\n+ bin/\n+ data/**/*.pak\n  + config/*.toml\n- *.pdb\n");
+
+    let rules = RuleSet::from_file(base.join("scan.rules")).unwrap();
+    let data = generate_file_data_with_rules(&build, &rules).unwrap();
+    let sep = std::path::MAIN_SEPARATOR;
+    let paths: Vec<String> = data.root.as_ref().unwrap().files().iter().map(|file| file.get_path()).collect();
+    assert_eq!(paths, vec![
+        format!(".{}bin{}app.exe", sep, sep),
+        format!(".{}config{}app.toml", sep, sep),
+        format!(".{}data{}maps{}a.pak", sep, sep, sep),
+    ]);
+    // directories holding nothing included are left out
+    let names: Vec<&String> = data.root.as_ref().unwrap().children().iter().map(|child| child.name()).collect();
+    assert_eq!(names, vec!["bin", "config", "data"]);
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_cli_exit_codes() {
+    use std::process::Command;
+
+    let base = temp_dir("cli");
+    let release = base.join("release");
+    let manifest = base.join("release.bin.gz");
+    write(&release.join("a.txt"), "a");
+
+    let run = |args: &[&std::ffi::OsStr]| Command::new(env!("CARGO_BIN_EXE_release")).args(args).output().unwrap();
+    let scan = run(&["scan".as_ref(), release.as_os_str(), "-o".as_ref(), manifest.as_os_str()]);
+    assert_eq!(scan.status.code(), Some(0));
+
+    let same = run(&["diff".as_ref(), release.as_os_str(), manifest.as_os_str()]);
+    assert_eq!(same.status.code(), Some(0));
+    assert!(same.stdout.is_empty());
+
+    write(&release.join("b.txt"), "b");
+    let changed = run(&["diff".as_ref(), release.as_os_str(), manifest.as_os_str()]);
+    assert_eq!(changed.status.code(), Some(1));
+    assert_eq!(String::from_utf8(changed.stdout).unwrap().trim(), format!("A: .{}b.txt", std::path::MAIN_SEPARATOR));
+
+    let quiet = run(&["--quiet".as_ref(), "diff".as_ref(), release.as_os_str(), manifest.as_os_str()]);
+    assert_eq!(quiet.status.code(), Some(1));
+    assert!(quiet.stdout.is_empty() && quiet.stderr.is_empty());
+    let info = run(&["info".as_ref(), manifest.as_os_str()]);
+    assert!(String::from_utf8(info.stdout).unwrap().contains("Files: 1"));
+    let quiet_info = run(&["-q".as_ref(), "info".as_ref(), manifest.as_os_str()]);
+    assert_eq!(quiet_info.status.code(), Some(0));
+    assert!(quiet_info.stdout.is_empty());
+
+    let missing = run(&["diff".as_ref(), base.join("missing").as_os_str(), manifest.as_os_str()]);
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(String::from_utf8(missing.stderr).unwrap().starts_with("error: "));
+
+    // a bundle is refused for an installed version it does not upgrade from
+    let source = FileData::load(&manifest).unwrap();
+    let target = generate_file_data_from_path(&release, &Vec::new()).unwrap();
+    let bundle = base.join("patch.bin.gz");
+    PatchBundle::create(&release, &source, &target).unwrap().save(&bundle).unwrap();
+    let mut other = FileData::load(&manifest).unwrap();
+    other.version = 5;
+    let other_manifest = base.join("other.bin.gz");
+    other.save(&other_manifest).unwrap();
+    let refused = run(&["apply".as_ref(), bundle.as_os_str(), release.as_os_str(), other_manifest.as_os_str()]);
+    assert_eq!(refused.status.code(), Some(2));
+    assert!(String::from_utf8(refused.stderr).unwrap().contains("Patch upgrades version 0, not version 5"));
+
+    // a scan reports a name it cannot store instead of panicking
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        fs::write(release.join(OsStr::from_bytes(b"bad\xff.txt")), "bad").unwrap();
+        let scanned = generate_file_data_from_path(&release, &Vec::new());
+        assert!(scanned.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidData));
+        let bad = run(&["scan".as_ref(), release.as_os_str(), "-o".as_ref(), manifest.as_os_str()]);
+        assert_eq!(bad.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&bad.stderr).starts_with("error: "));
+    }
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_cli_build() {
+    use std::process::Command;
+
+    let base = temp_dir("cli_build");
+    let project = base.join("project");
+    let game = project.join("build").join("game");
+    let tools = project.join("build").join("tools");
+    write(&game.join("bin").join("app.exe"), "app");
+    write(&game.join("bin").join("app.pdb"), "symbols");
+    write(&game.join("data").join("a.pak"), "data");
+    write(&game.join("notes.txt"), "not shipped");
+    write(&tools.join("tool.exe"), "tool");
+    write(&tools.join("scratch.tmp"), "scratch");
+    write(&tools.join(".releaseignore"), "*.tmp\n.releaseignore\n");
+    write(&project.join("release.toml"), r#"
+        [targets.game]
+        path = "build/game"
+        manifest = "releases/game.bin.gz"
+        output = "releases/game-patch"
+        include = ["bin/", "data/**"]
+        ignore = ["*.pdb"]
+
+        [targets.tools]
+        path = "build/tools"
+        manifest = "releases/tools.bin.gz"
+        output = "releases/tools.tar.gz"
+        format = "tar-gz"
+    "#);
+
+    // paths in the config are relative to it, not to the working directory
+    let config = project.join("release.toml");
+    let build = |target: &str| Command::new(env!("CARGO_BIN_EXE_release"))
+        .current_dir(&base)
+        .args(["build".as_ref(), target.as_ref(), "-c".as_ref(), config.as_os_str()])
+        .output()
+        .unwrap();
+    let files = |data: &FileData| {
+        let mut files: Vec<String> = data.root.as_ref().unwrap().files().iter().map(|file| file.name.clone()).collect();
+        files.sort();
+        files
+    };
+
+    let releases = project.join("releases");
+    assert_eq!(build("game").status.code(), Some(0));
+    assert_eq!(files(&FileData::load(releases.join("game.bin.gz")).unwrap()), vec!["a.pak", "app.exe"]);
+    assert!(releases.join("game-patch").join("bin").join("app.exe").is_file());
+    assert!(releases.join("game-patch").join("data").join("a.pak").is_file());
+    assert!(!releases.join("game-patch").join("bin").join("app.pdb").exists());
+    assert!(!releases.join("game-patch").join("notes.txt").exists());
+
+    assert_eq!(build("tools").status.code(), Some(0));
+    assert_eq!(files(&FileData::load(releases.join("tools.bin.gz")).unwrap()), vec!["tool.exe"]);
+    let installed = base.join("installed");
+    fs::create_dir_all(&installed).unwrap();
+    apply_archive(releases.join("tools.tar.gz"), &installed, ArchiveFormat::TarGz).unwrap();
+    assert_eq!(fs::read_to_string(installed.join("tool.exe")).unwrap(), "tool");
+    assert!(!installed.join("scratch.tmp").exists());
+
+    // the next build patches from the manifest the previous one saved
+    write(&game.join("data").join("a.pak"), "new data");
+    fs::remove_dir_all(releases.join("game-patch")).unwrap();
+    assert_eq!(build("game").status.code(), Some(0));
+    assert!(releases.join("game-patch").join("data").join("a.pak").is_file());
+    assert!(!releases.join("game-patch").join("bin").join("app.exe").exists());
+
+    let missing = build("missing");
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(String::from_utf8(missing.stderr).unwrap().contains("game, tools"));
+
+    fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn test_diff_records() {
+    let base = temp_dir("records");
+    let source = base.join("source");
+    let target = base.join("target");
+    write(&source.join("changed.txt"), "old");
+    write(&source.join("moved.txt"), "moved content");
+    write(&source.join("gone").join("a.txt"), "removed");
+    write(&target.join("changed.txt"), "newer");
+    write(&target.join("sub").join("moved.txt"), "moved content");
+
+    let source_data = generate_file_data_from_path(&source, &Vec::new()).unwrap();
+    let target_data = generate_file_data_from_path(&target, &Vec::new()).unwrap();
+    let diffs = source_data.diff(&target_data).unwrap();
+    let records = DiffRecord::collect(&diffs, &source_data, &target_data);
+
+    let changed = records.iter().find(|record| record.path == "changed.txt").unwrap();
+    assert_eq!(changed.status, DiffStatus::Modified);
+    assert_eq!((changed.old_size, changed.new_size), (Some(3), Some(5)));
+    assert_ne!(changed.old_hash, changed.new_hash);
+    let moved = records.iter().find(|record| record.status == DiffStatus::Renamed).unwrap();
+    assert_eq!((moved.old_path.as_deref(), moved.path.as_str()), (Some("moved.txt"), "sub/moved.txt"));
+    assert_eq!(moved.old_hash, moved.new_hash);
+    assert!(records.iter().any(|record| record.status == DiffStatus::Deleted && record.path == "gone/"));
+
+    let mut out = Vec::new();
+    write_diffs(&records, DiffFormat::NameStatus, &mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("R\tmoved.txt\tsub/moved.txt\n"));
+    for (diff, record) in diffs.iter().zip(&records) {
+        assert!(diff.to_string().starts_with(&format!("{}: ", record.status.letter())));
+    }
+
+    fs::remove_dir_all(base).unwrap();
+}